@@ -0,0 +1,21 @@
+use std::process::Command;
+
+fn main() {
+    // docker builds don't include the .git directory, so the hash can be passed in instead
+    let git_hash = std::env::var("GIT_HASH")
+        .ok()
+        .or_else(|| {
+            Command::new("git")
+                .args(["rev-parse", "--short", "HEAD"])
+                .output()
+                .ok()
+                .filter(|output| output.status.success())
+                .and_then(|output| String::from_utf8(output.stdout).ok())
+        })
+        .unwrap_or_else(|| String::from("unknown"));
+
+    println!("cargo:rustc-env=GIT_HASH={}", git_hash.trim());
+    println!("cargo:rerun-if-env-changed=GIT_HASH");
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs");
+}
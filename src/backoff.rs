@@ -0,0 +1,37 @@
+use std::time::Duration;
+
+/// Exponential back-off, doubled for every consecutive failure until it reaches the maximum
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Backoff {
+    base: Duration,
+    max: Duration,
+}
+
+impl Backoff {
+    pub(crate) const fn new(base: Duration, max: Duration) -> Self {
+        Self { base, max }
+    }
+
+    /// how long to back off after this many consecutive failures, the first one waits `base`
+    pub(crate) fn after(&self, failures: u32) -> Duration {
+        self.base
+            .checked_mul(2u32.saturating_pow(failures.saturating_sub(1)))
+            .unwrap_or(self.max)
+            .min(self.max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn doubles_up_to_the_maximum() {
+        let backoff = Backoff::new(Duration::from_secs(5), Duration::from_secs(60));
+
+        assert_eq!(backoff.after(1), Duration::from_secs(5));
+        assert_eq!(backoff.after(3), Duration::from_secs(20));
+        assert_eq!(backoff.after(5), Duration::from_secs(60));
+        assert_eq!(backoff.after(100), Duration::from_secs(60));
+    }
+}
@@ -0,0 +1,136 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use derive_more::{Display, From};
+
+/// A store the cache can keep its serialized values in
+#[async_trait]
+pub trait Backend: Send + Sync {
+    /// a short name for logging and status reporting
+    fn name(&self) -> &'static str;
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, BackendError>;
+
+    /// store a value, it never expires if no ttl is given
+    async fn set(
+        &self,
+        key: &str,
+        value: Vec<u8>,
+        ttl: Option<Duration>,
+    ) -> Result<(), BackendError>;
+
+    async fn delete(&self, key: &str) -> Result<(), BackendError>;
+
+    /// list the keys starting with this prefix, expired entries are removed along the way
+    async fn keys(&self, prefix: &str) -> Result<Vec<String>, BackendError>;
+
+    /// delete every key starting with this prefix, returns how many were deleted
+    async fn delete_prefix(&self, prefix: &str) -> Result<usize, BackendError> {
+        let keys = self.keys(prefix).await?;
+        for key in &keys {
+            self.delete(key).await?;
+        }
+
+        Ok(keys.len())
+    }
+
+    /// check if the backend is reachable
+    async fn ping(&self) -> Result<(), BackendError>;
+
+    /// take a lock shared with other bot instances, returns false if someone else holds it
+    /// backends that aren't shared between instances don't need one
+    async fn lock(&self, _key: &str, _token: &str, _ttl: Duration) -> Result<bool, BackendError> {
+        Ok(true)
+    }
+
+    /// release a lock, but only if it's still held with this token
+    async fn unlock(&self, _key: &str, _token: &str) -> Result<(), BackendError> {
+        Ok(())
+    }
+
+    /// health of the individual servers, for backends spread over more than one
+    async fn nodes(&self) -> Result<Vec<NodeStatus>, BackendError> {
+        Ok(Vec::new())
+    }
+}
+
+/// Health of a single server behind a backend
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct NodeStatus {
+    pub address: String,
+    pub role: &'static str,
+    pub healthy: bool,
+}
+
+#[async_trait]
+impl<B: Backend + ?Sized> Backend for Box<B> {
+    fn name(&self) -> &'static str {
+        (**self).name()
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, BackendError> {
+        (**self).get(key).await
+    }
+
+    async fn set(
+        &self,
+        key: &str,
+        value: Vec<u8>,
+        ttl: Option<Duration>,
+    ) -> Result<(), BackendError> {
+        (**self).set(key, value, ttl).await
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), BackendError> {
+        (**self).delete(key).await
+    }
+
+    async fn keys(&self, prefix: &str) -> Result<Vec<String>, BackendError> {
+        (**self).keys(prefix).await
+    }
+
+    async fn delete_prefix(&self, prefix: &str) -> Result<usize, BackendError> {
+        (**self).delete_prefix(prefix).await
+    }
+
+    async fn ping(&self) -> Result<(), BackendError> {
+        (**self).ping().await
+    }
+
+    async fn lock(&self, key: &str, token: &str, ttl: Duration) -> Result<bool, BackendError> {
+        (**self).lock(key, token, ttl).await
+    }
+
+    async fn unlock(&self, key: &str, token: &str) -> Result<(), BackendError> {
+        (**self).unlock(key, token).await
+    }
+
+    async fn nodes(&self) -> Result<Vec<NodeStatus>, BackendError> {
+        (**self).nodes().await
+    }
+}
+
+#[derive(Debug, Display, From)]
+pub enum BackendError {
+    #[display(fmt = "redis error: {}", _0)]
+    Redis(redis::RedisError),
+    #[display(fmt = "unable to get a redis connection: {}", _0)]
+    Pool(deadpool_redis::PoolError),
+    #[display(fmt = "unable to create a redis connection pool: {}", _0)]
+    CreatePool(deadpool_redis::CreatePoolError),
+    #[display(fmt = "no sentinel knows the redis master `{}`", _0)]
+    #[from(ignore)]
+    NoMaster(String),
+    #[display(fmt = "io error: {}", _0)]
+    Io(std::io::Error),
+    #[display(fmt = "cache task failed: {}", _0)]
+    Task(tokio::task::JoinError),
+    #[display(fmt = "a key of {} bytes is too long for this backend", _0)]
+    #[from(ignore)]
+    KeyTooLong(usize),
+    #[display(fmt = "no response within {:?}", _0)]
+    #[from(ignore)]
+    Timeout(Duration),
+}
+
+impl std::error::Error for BackendError {}
@@ -0,0 +1,328 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use redis::cluster::{ClusterClient, ClusterConnection};
+use redis::{cmd, RedisResult};
+
+use super::backend::{Backend, BackendError, NodeStatus};
+use super::redis::{escape_pattern, node_url, SCAN_COUNT, UNLOCK};
+
+/// How long a node may take to answer, commands share one connection so a hung node would block all
+/// of them. The redis crate can't bound connecting to the cluster itself, only to single nodes.
+const NODE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Spreads the cache over a Redis Cluster
+///
+/// The redis crate only offers a blocking cluster connection, so commands run on tokio's blocking
+/// threads one at a time. That's plenty for the handful of commands the bot sends per message.
+pub struct ClusterBackend {
+    client: Arc<ClusterClient>,
+    /// made once it's needed, and dropped after a connection error so it's made again
+    connection: Arc<Mutex<Option<ClusterConnection>>>,
+    /// credentials used to connect to single nodes, to scan their keys
+    url: String,
+}
+
+/// A node as reported by `CLUSTER NODES`
+#[derive(Debug, PartialEq)]
+struct ClusterNode {
+    host: String,
+    port: u16,
+    master: bool,
+    healthy: bool,
+}
+
+impl ClusterBackend {
+    /// checks the node urls, connections are only made once they're needed
+    pub fn new(nodes: &[&str]) -> RedisResult<Self> {
+        Ok(Self {
+            client: Arc::new(ClusterClient::open(nodes.to_vec())?),
+            connection: Arc::new(Mutex::new(None)),
+            url: nodes.first().copied().unwrap_or_default().to_owned(),
+        })
+    }
+
+    /// run commands on the cluster connection, without blocking the runtime
+    async fn run<T, F>(&self, f: F) -> Result<T, BackendError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut ClusterConnection) -> RedisResult<T> + Send + 'static,
+    {
+        let client = self.client.clone();
+        let connection = self.connection.clone();
+
+        let res = tokio::task::spawn_blocking(move || {
+            let mut connection = connection.lock().expect("cluster connection poisoned");
+            if connection.is_none() {
+                let new = client.get_connection()?;
+                new.set_read_timeout(Some(NODE_TIMEOUT))?;
+                new.set_write_timeout(Some(NODE_TIMEOUT))?;
+                *connection = Some(new);
+            }
+
+            let res = f(connection
+                .as_mut()
+                .expect("cluster connection was just made"));
+            if matches!(&res, Err(err) if err.is_io_error() || err.is_connection_dropped()) {
+                *connection = None;
+            }
+            res
+        })
+        .await?;
+
+        Ok(res?)
+    }
+
+    /// scan every healthy master for the keys starting with a prefix, handing each batch to `each`
+    async fn scan<T, F>(&self, prefix: &str, init: T, mut each: F) -> Result<T, BackendError>
+    where
+        T: Send + 'static,
+        F: FnMut(&mut redis::Connection, Vec<String>, &mut T) -> RedisResult<()> + Send + 'static,
+    {
+        // a failed master can't be reached, its replica takes over once the cluster notices
+        let (masters, failed): (Vec<ClusterNode>, Vec<ClusterNode>) = self
+            .cluster_nodes()
+            .await?
+            .into_iter()
+            .filter(|node| node.master)
+            .partition(|node| node.healthy);
+        for node in failed {
+            warn!(
+                "skipping the failed redis master {}:{}",
+                node.host, node.port
+            );
+        }
+        let masters: Vec<String> = masters
+            .into_iter()
+            .map(|node| node_url(Some(&self.url), &node.host, node.port))
+            .collect();
+        let pattern = format!("{}*", escape_pattern(prefix));
+
+        let res = tokio::task::spawn_blocking(move || -> RedisResult<T> {
+            let mut acc = init;
+
+            for master in masters {
+                let mut conn = redis::Client::open(master.as_str())?
+                    .get_connection_with_timeout(NODE_TIMEOUT)?;
+                conn.set_read_timeout(Some(NODE_TIMEOUT))?;
+                conn.set_write_timeout(Some(NODE_TIMEOUT))?;
+
+                let mut cursor: u64 = 0;
+                loop {
+                    let (next, batch): (u64, Vec<String>) = cmd("SCAN")
+                        .arg(cursor)
+                        .arg("MATCH")
+                        .arg(&pattern)
+                        .arg("COUNT")
+                        .arg(SCAN_COUNT)
+                        .query(&mut conn)?;
+
+                    if !batch.is_empty() {
+                        each(&mut conn, batch, &mut acc)?;
+                    }
+
+                    if next == 0 {
+                        break;
+                    }
+                    cursor = next;
+                }
+            }
+
+            Ok(acc)
+        })
+        .await?;
+
+        Ok(res?)
+    }
+
+    async fn cluster_nodes(&self) -> Result<Vec<ClusterNode>, BackendError> {
+        let nodes: String = self
+            .run(|conn| cmd("CLUSTER").arg("NODES").query(conn))
+            .await?;
+
+        Ok(parse_cluster_nodes(&nodes))
+    }
+}
+
+/// parse the output of `CLUSTER NODES`, lines that can't be parsed are skipped
+fn parse_cluster_nodes(nodes: &str) -> Vec<ClusterNode> {
+    nodes
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 8 {
+                return None;
+            }
+
+            // formatted as `ip:port@cport`, older versions leave out the cluster bus port
+            let address = fields[1].split('@').next()?;
+            let (host, port) = address.rsplit_once(':')?;
+            let flags: Vec<&str> = fields[2].split(',').collect();
+
+            Some(ClusterNode {
+                host: host.to_owned(),
+                port: port.parse().ok()?,
+                master: flags.contains(&"master"),
+                healthy: fields[7] == "connected"
+                    && !flags
+                        .iter()
+                        .any(|flag| matches!(*flag, "fail" | "fail?" | "noaddr" | "handshake")),
+            })
+        })
+        .collect()
+}
+
+#[async_trait]
+impl Backend for ClusterBackend {
+    fn name(&self) -> &'static str {
+        "redis cluster"
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, BackendError> {
+        let key = key.to_owned();
+
+        self.run(move |conn| cmd("GET").arg(key).query(conn)).await
+    }
+
+    async fn set(
+        &self,
+        key: &str,
+        value: Vec<u8>,
+        ttl: Option<Duration>,
+    ) -> Result<(), BackendError> {
+        let key = key.to_owned();
+
+        self.run(move |conn| match ttl {
+            Some(ttl) => cmd("SETEX")
+                .arg(key)
+                .arg(ttl.as_secs())
+                .arg(value)
+                .query(conn),
+            None => cmd("SET").arg(key).arg(value).query(conn),
+        })
+        .await
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), BackendError> {
+        let key = key.to_owned();
+
+        self.run(move |conn| cmd("DEL").arg(key).query(conn)).await
+    }
+
+    /// every master holds its own share of the keys, so all of them are scanned
+    async fn keys(&self, prefix: &str) -> Result<Vec<String>, BackendError> {
+        self.scan(
+            prefix,
+            Vec::new(),
+            |_, mut batch, keys: &mut Vec<String>| {
+                keys.append(&mut batch);
+                Ok(())
+            },
+        )
+        .await
+    }
+
+    /// the keys of a batch can be in different slots, so they're unlinked one by one in a pipeline
+    async fn delete_prefix(&self, prefix: &str) -> Result<usize, BackendError> {
+        self.scan(prefix, 0, |conn, batch, deleted: &mut usize| {
+            let mut pipe = redis::pipe();
+            for key in batch {
+                pipe.cmd("UNLINK").arg(key);
+            }
+            let unlinked: Vec<usize> = pipe.query(conn)?;
+            *deleted += unlinked.into_iter().sum::<usize>();
+            Ok(())
+        })
+        .await
+    }
+
+    async fn ping(&self) -> Result<(), BackendError> {
+        self.run(|conn| cmd("PING").query(conn)).await
+    }
+
+    async fn lock(&self, key: &str, token: &str, ttl: Duration) -> Result<bool, BackendError> {
+        let key = key.to_owned();
+        let token = token.to_owned();
+
+        let res: Option<String> = self
+            .run(move |conn| {
+                cmd("SET")
+                    .arg(key)
+                    .arg(token)
+                    .arg("NX")
+                    .arg("PX")
+                    .arg(ttl.as_millis() as u64)
+                    .query(conn)
+            })
+            .await?;
+
+        Ok(res.is_some())
+    }
+
+    async fn unlock(&self, key: &str, token: &str) -> Result<(), BackendError> {
+        let key = key.to_owned();
+        let token = token.to_owned();
+
+        self.run(move |conn| UNLOCK.key(key).arg(token).invoke(conn))
+            .await
+    }
+
+    async fn nodes(&self) -> Result<Vec<NodeStatus>, BackendError> {
+        Ok(self
+            .cluster_nodes()
+            .await?
+            .into_iter()
+            .map(|node| NodeStatus {
+                address: format!("{}:{}", node.host, node.port),
+                role: if node.master { "master" } else { "replica" },
+                healthy: node.healthy,
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_cluster_nodes() {
+        let nodes = "\
+07c37dfeb235213a872192d90877d0cd55635b91 127.0.0.1:30004@31004 slave e7d1eecce10fd6bb5eb35b9f99a514335d9ba9ca 0 1426238317239 4 connected
+67ed2db8d677e59ec4a4cefb06858cf2a1a89fa1 127.0.0.1:30002@31002 master - 0 1426238316232 2 connected 5461-10922
+e7d1eecce10fd6bb5eb35b9f99a514335d9ba9ca 127.0.0.1:30001@31001 myself,master - 0 0 1 connected 0-5460
+292f8b365bb7edb5e285caf0b7e6ddc7265d2f4f 127.0.0.1:30003 master,fail - 1426238316232 1426238318243 3 disconnected 10923-16383
+";
+
+        assert_eq!(
+            parse_cluster_nodes(nodes),
+            vec![
+                ClusterNode {
+                    host: String::from("127.0.0.1"),
+                    port: 30004,
+                    master: false,
+                    healthy: true,
+                },
+                ClusterNode {
+                    host: String::from("127.0.0.1"),
+                    port: 30002,
+                    master: true,
+                    healthy: true,
+                },
+                ClusterNode {
+                    host: String::from("127.0.0.1"),
+                    port: 30001,
+                    master: true,
+                    healthy: true,
+                },
+                ClusterNode {
+                    host: String::from("127.0.0.1"),
+                    port: 30003,
+                    master: true,
+                    healthy: false,
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,147 @@
+use std::io::{Read, Write};
+
+use derive_more::{Display, From};
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::config::CacheFormat;
+
+/// Encoded values start with one of these marker bytes
+/// JSON never starts with them, so entries without a marker are read as (legacy) JSON
+const MSGPACK: u8 = 0x01;
+const MSGPACK_DEFLATE: u8 = 0x02;
+const JSON_DEFLATE: u8 = 0x03;
+
+/// Turns cached values into bytes and back
+#[derive(Debug, Clone, Copy)]
+pub struct Codec {
+    format: CacheFormat,
+    /// values larger than this amount of bytes are compressed, None disables compression
+    compress_above: Option<usize>,
+}
+
+#[derive(Debug, Display, From)]
+pub enum CodecError {
+    #[display(fmt = "json error: {}", _0)]
+    Json(serde_json::Error),
+    #[display(fmt = "msgpack encode error: {}", _0)]
+    Encode(rmp_serde::encode::Error),
+    #[display(fmt = "msgpack decode error: {}", _0)]
+    Decode(rmp_serde::decode::Error),
+    #[display(fmt = "compression error: {}", _0)]
+    Compression(std::io::Error),
+}
+
+impl std::error::Error for CodecError {}
+
+impl Codec {
+    pub fn new(format: CacheFormat, compress_above: Option<usize>) -> Self {
+        Self {
+            format,
+            compress_above,
+        }
+    }
+
+    pub fn encode<T: Serialize>(&self, value: &T) -> Result<Vec<u8>, CodecError> {
+        let (marker, compressed_marker, bytes) = match self.format {
+            CacheFormat::Json => (None, JSON_DEFLATE, serde_json::to_vec(value)?),
+            CacheFormat::Msgpack => (
+                Some(MSGPACK),
+                MSGPACK_DEFLATE,
+                rmp_serde::to_vec_named(value)?,
+            ),
+        };
+
+        match self.compress_above {
+            Some(threshold) if bytes.len() > threshold => {
+                let mut encoder = DeflateEncoder::new(vec![compressed_marker], Compression::fast());
+                encoder.write_all(&bytes)?;
+                Ok(encoder.finish()?)
+            }
+            _ => match marker {
+                Some(marker) => {
+                    let mut encoded = Vec::with_capacity(bytes.len() + 1);
+                    encoded.push(marker);
+                    encoded.extend_from_slice(&bytes);
+                    Ok(encoded)
+                }
+                None => Ok(bytes),
+            },
+        }
+    }
+
+    /// decode a value in any of the supported formats, regardless of the configured one
+    pub fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, CodecError> {
+        match bytes.split_first() {
+            Some((&MSGPACK, bytes)) => Ok(rmp_serde::from_slice(bytes)?),
+            Some((&MSGPACK_DEFLATE, bytes)) => Ok(rmp_serde::from_slice(&Codec::inflate(bytes)?)?),
+            Some((&JSON_DEFLATE, bytes)) => Ok(serde_json::from_slice(&Codec::inflate(bytes)?)?),
+            _ => Ok(serde_json::from_slice(bytes)?),
+        }
+    }
+
+    fn inflate(bytes: &[u8]) -> Result<Vec<u8>, CodecError> {
+        let mut inflated = Vec::new();
+        DeflateDecoder::new(bytes).read_to_end(&mut inflated)?;
+        Ok(inflated)
+    }
+}
+
+impl Default for Codec {
+    fn default() -> Self {
+        Self::new(CacheFormat::Msgpack, Some(1024))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Kitten {
+        name: String,
+        toys: Vec<String>,
+    }
+
+    fn kitten() -> Kitten {
+        Kitten {
+            name: String::from("Tom"),
+            toys: vec![String::from("yarn"); 100],
+        }
+    }
+
+    #[test]
+    fn roundtrips_every_format() {
+        for format in [CacheFormat::Json, CacheFormat::Msgpack] {
+            for compress_above in [None, Some(16)] {
+                let codec = Codec::new(format, compress_above);
+                let encoded = codec.encode(&kitten()).unwrap();
+
+                assert_eq!(Codec::decode::<Kitten>(&encoded).unwrap(), kitten());
+            }
+        }
+    }
+
+    #[test]
+    fn compresses_large_values() {
+        let plain = Codec::new(CacheFormat::Msgpack, None)
+            .encode(&kitten())
+            .unwrap();
+        let compressed = Codec::new(CacheFormat::Msgpack, Some(16))
+            .encode(&kitten())
+            .unwrap();
+
+        assert_eq!(compressed[0], MSGPACK_DEFLATE);
+        assert!(compressed.len() < plain.len());
+    }
+
+    #[test]
+    fn reads_legacy_json() {
+        let legacy = serde_json::to_vec(&kitten()).unwrap();
+
+        assert_eq!(Codec::decode::<Kitten>(&legacy).unwrap(), kitten());
+    }
+}
@@ -0,0 +1,234 @@
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use async_trait::async_trait;
+use sha2::{Digest, Sha256};
+
+use super::backend::{Backend, BackendError};
+
+/// Stores every entry in its own file, so the cache survives restarts without running Redis
+///
+/// Each file starts with the expiry time as a big endian unix timestamp (0 meaning it never expires),
+/// followed by the length of the key as a big endian u16, the key itself and the value.
+pub struct FileBackend {
+    directory: PathBuf,
+}
+
+const HEADER_LEN: usize = 10;
+
+struct Entry {
+    expires_at: u64,
+    key: String,
+    value: Vec<u8>,
+}
+
+impl Entry {
+    fn encode(&self) -> Vec<u8> {
+        let mut contents = Vec::with_capacity(HEADER_LEN + self.key.len() + self.value.len());
+        contents.extend_from_slice(&self.expires_at.to_be_bytes());
+        contents.extend_from_slice(&(self.key.len() as u16).to_be_bytes());
+        contents.extend_from_slice(self.key.as_bytes());
+        contents.extend_from_slice(&self.value);
+        contents
+    }
+
+    /// returns None for files that aren't valid cache entries
+    fn decode(mut contents: Vec<u8>) -> Option<Self> {
+        if contents.len() < HEADER_LEN {
+            return None;
+        }
+
+        let mut expires_at = [0u8; 8];
+        expires_at.copy_from_slice(&contents[..8]);
+        let mut key_len = [0u8; 2];
+        key_len.copy_from_slice(&contents[8..HEADER_LEN]);
+        let key_end = HEADER_LEN + u16::from_be_bytes(key_len) as usize;
+
+        if contents.len() < key_end {
+            return None;
+        }
+
+        let value = contents.split_off(key_end);
+        let key = String::from_utf8(contents.split_off(HEADER_LEN)).ok()?;
+
+        Some(Self {
+            expires_at: u64::from_be_bytes(expires_at),
+            key,
+            value,
+        })
+    }
+
+    fn is_expired(&self) -> bool {
+        self.expires_at != 0 && self.expires_at <= FileBackend::now()
+    }
+}
+
+impl FileBackend {
+    /// creates the cache directory if it doesn't exist yet
+    pub fn new<P: Into<PathBuf>>(directory: P) -> std::io::Result<Self> {
+        let directory = directory.into();
+        std::fs::create_dir_all(&directory)?;
+
+        Ok(Self { directory })
+    }
+
+    /// keys can contain anything, so the file name is the hash of the key
+    fn path(&self, key: &str) -> PathBuf {
+        self.directory
+            .join(format!("{:x}", Sha256::digest(key.as_bytes())))
+    }
+
+    /// read an entry, invalid and expired entries are removed
+    async fn read(path: &Path) -> Result<Option<Entry>, BackendError> {
+        let contents = match tokio::fs::read(path).await {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+
+        match Entry::decode(contents) {
+            Some(entry) if !entry.is_expired() => Ok(Some(entry)),
+            _ => {
+                FileBackend::remove(path).await?;
+                Ok(None)
+            }
+        }
+    }
+
+    async fn remove(path: &Path) -> Result<(), BackendError> {
+        match tokio::fs::remove_file(path).await {
+            Err(err) if err.kind() != ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs()
+    }
+}
+
+#[async_trait]
+impl Backend for FileBackend {
+    fn name(&self) -> &'static str {
+        "file"
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, BackendError> {
+        let entry = FileBackend::read(&self.path(key)).await?;
+
+        Ok(entry.map(|entry| entry.value))
+    }
+
+    async fn set(
+        &self,
+        key: &str,
+        value: Vec<u8>,
+        ttl: Option<Duration>,
+    ) -> Result<(), BackendError> {
+        // the key length is stored as a u16
+        if key.len() > u16::MAX as usize {
+            return Err(BackendError::KeyTooLong(key.len()));
+        }
+
+        let entry = Entry {
+            expires_at: ttl
+                .map(|ttl| FileBackend::now() + ttl.as_secs())
+                .unwrap_or(0),
+            key: key.to_owned(),
+            value,
+        };
+
+        // write to a temporary file first, so readers never see a half written entry
+        // it's unique to this write, so concurrent writes of the same key don't mix
+        let path = self.path(key);
+        let tmp_path = path.with_extension(format!("{:016x}.tmp", rand::random::<u64>()));
+        let written = match tokio::fs::write(&tmp_path, entry.encode()).await {
+            Ok(()) => tokio::fs::rename(&tmp_path, &path).await,
+            Err(err) => Err(err),
+        };
+        if let Err(err) = written {
+            FileBackend::remove(&tmp_path).await?;
+            return Err(err.into());
+        }
+
+        Ok(())
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), BackendError> {
+        FileBackend::remove(&self.path(key)).await
+    }
+
+    async fn keys(&self, prefix: &str) -> Result<Vec<String>, BackendError> {
+        let mut keys = Vec::new();
+        let mut dir = tokio::fs::read_dir(&self.directory).await?;
+
+        while let Some(file) = dir.next_entry().await? {
+            let path = file.path();
+            if path.extension().is_some() {
+                continue;
+            }
+
+            if let Some(entry) = FileBackend::read(&path).await? {
+                if entry.key.starts_with(prefix) {
+                    keys.push(entry.key);
+                }
+            }
+        }
+
+        Ok(keys)
+    }
+
+    async fn ping(&self) -> Result<(), BackendError> {
+        Ok(tokio::fs::create_dir_all(&self.directory).await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn stores_and_expires_entries() {
+        let directory =
+            std::env::temp_dir().join(format!("dinkelberg-cache-{}", rand::random::<u64>()));
+        let backend = FileBackend::new(&directory).unwrap();
+
+        backend.set("cats", b"meow".to_vec(), None).await.unwrap();
+        backend
+            .set("dogs", b"woof".to_vec(), Some(Duration::from_secs(0)))
+            .await
+            .unwrap();
+
+        assert_eq!(backend.get("cats").await.unwrap(), Some(b"meow".to_vec()));
+        assert_eq!(backend.get("dogs").await.unwrap(), None);
+        assert_eq!(backend.get("birds").await.unwrap(), None);
+
+        backend
+            .set("cats.tom", b"meow".to_vec(), None)
+            .await
+            .unwrap();
+        assert_eq!(
+            backend.keys("cats.").await.unwrap(),
+            vec![String::from("cats.tom")]
+        );
+
+        backend.delete("cats").await.unwrap();
+        assert_eq!(backend.get("cats").await.unwrap(), None);
+
+        let long_key = "c".repeat(u16::MAX as usize + 1);
+        assert!(backend.set(&long_key, Vec::new(), None).await.is_err());
+
+        let (first, second) = tokio::join!(
+            backend.set("birds", b"tweet".to_vec(), None),
+            backend.set("birds", b"chirp".to_vec(), None),
+        );
+        assert!(first.is_ok() && second.is_ok());
+        assert!(backend.get("birds").await.unwrap().is_some());
+
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
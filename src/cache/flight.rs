@@ -0,0 +1,71 @@
+use std::any::Any;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use tokio::sync::broadcast::{self, Receiver, Sender};
+
+/// Lets only one task at a time fetch the value for a key, others wait for it and share its result
+///
+/// The result is handed over directly instead of through the backend, so waiting tasks get it even
+/// when it couldn't be stored, or when fetching it failed.
+#[derive(Default)]
+pub struct SingleFlight {
+    flights: Mutex<HashMap<String, Box<dyn Any + Send>>>,
+}
+
+/// What joining a flight for a key turned out to be
+pub enum Flight<'a, V> {
+    /// nobody was fetching the value, so it's up to us
+    Leading(FlightGuard<'a, V>),
+    /// another task is fetching it, the result is sent here
+    Waiting(Receiver<V>),
+}
+
+/// Held while fetching, landing it shares the result with everyone waiting for it
+pub struct FlightGuard<'a, V> {
+    flights: &'a SingleFlight,
+    key: String,
+    landed: Sender<V>,
+}
+
+impl SingleFlight {
+    pub fn join<V: Clone + Send + 'static>(&self, key: &str) -> Flight<'_, V> {
+        let mut flights = self.flights.lock().expect("single flight poisoned");
+
+        if let Some(landed) = flights
+            .get(key)
+            .and_then(|flight| flight.downcast_ref::<Sender<V>>())
+        {
+            return Flight::Waiting(landed.subscribe());
+        }
+
+        let (landed, _) = broadcast::channel(1);
+        flights.insert(key.to_owned(), Box::new(landed.clone()));
+
+        Flight::Leading(FlightGuard {
+            flights: self,
+            key: key.to_owned(),
+            landed,
+        })
+    }
+}
+
+impl<V> FlightGuard<'_, V> {
+    /// hand the result to every task that joined while it was being fetched
+    pub fn land(self, value: V) {
+        let landed = self.landed.clone();
+        // later tasks start a flight of their own
+        drop(self);
+
+        // nobody waiting for it is fine
+        let _ = landed.send(value);
+    }
+}
+
+impl<V> Drop for FlightGuard<'_, V> {
+    /// a flight that's dropped without landing closes the channel, so waiting tasks can take over
+    fn drop(&mut self) {
+        let mut flights = self.flights.flights.lock().expect("single flight poisoned");
+        flights.remove(&self.key);
+    }
+}
@@ -0,0 +1,146 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use lru::LruCache;
+
+use super::backend::{Backend, BackendError};
+
+/// In-process cache, used when no Redis is configured
+/// Least recently used entries are evicted once the capacity is reached
+pub struct MemoryBackend {
+    entries: Mutex<LruCache<String, Entry>>,
+}
+
+struct Entry {
+    value: Vec<u8>,
+    expires_at: Option<Instant>,
+}
+
+impl Entry {
+    fn is_expired(&self) -> bool {
+        matches!(self.expires_at, Some(expires_at) if expires_at <= Instant::now())
+    }
+}
+
+impl MemoryBackend {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            entries: Mutex::new(LruCache::new(capacity)),
+        }
+    }
+
+    fn get_entry(&self, key: &str) -> Option<Vec<u8>> {
+        let mut entries = self.entries.lock().expect("memory cache poisoned");
+
+        match entries.get(key) {
+            Some(entry) if entry.is_expired() => {
+                entries.pop(key);
+                None
+            }
+            Some(entry) => Some(entry.value.clone()),
+            None => None,
+        }
+    }
+
+    fn set_entry(&self, key: &str, value: Vec<u8>, ttl: Option<Duration>) {
+        let mut entries = self.entries.lock().expect("memory cache poisoned");
+
+        entries.put(
+            key.to_owned(),
+            Entry {
+                value,
+                expires_at: ttl.map(|ttl| Instant::now() + ttl),
+            },
+        );
+    }
+
+    fn delete_entry(&self, key: &str) {
+        let mut entries = self.entries.lock().expect("memory cache poisoned");
+        entries.pop(key);
+    }
+
+    fn sweep(&self, prefix: &str) -> Vec<String> {
+        let mut entries = self.entries.lock().expect("memory cache poisoned");
+
+        let expired: Vec<String> = entries
+            .iter()
+            .filter(|(_, entry)| entry.is_expired())
+            .map(|(key, _)| key.clone())
+            .collect();
+
+        for key in expired {
+            entries.pop(&key);
+        }
+
+        entries
+            .iter()
+            .map(|(key, _)| key)
+            .filter(|key| key.starts_with(prefix))
+            .cloned()
+            .collect()
+    }
+}
+
+#[async_trait]
+impl Backend for MemoryBackend {
+    fn name(&self) -> &'static str {
+        "memory"
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, BackendError> {
+        Ok(self.get_entry(key))
+    }
+
+    async fn set(
+        &self,
+        key: &str,
+        value: Vec<u8>,
+        ttl: Option<Duration>,
+    ) -> Result<(), BackendError> {
+        self.set_entry(key, value, ttl);
+        Ok(())
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), BackendError> {
+        self.delete_entry(key);
+        Ok(())
+    }
+
+    async fn keys(&self, prefix: &str) -> Result<Vec<String>, BackendError> {
+        Ok(self.sweep(prefix))
+    }
+
+    async fn ping(&self) -> Result<(), BackendError> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expired_entries_are_not_returned() {
+        let store = MemoryBackend::new(10);
+        store.set_entry("cats", b"meow".to_vec(), Some(Duration::from_secs(0)));
+        store.set_entry("dogs", b"woof".to_vec(), None);
+
+        assert_eq!(store.sweep(""), vec![String::from("dogs")]);
+        assert_eq!(store.get_entry("cats"), None);
+        assert_eq!(store.get_entry("dogs"), Some(b"woof".to_vec()));
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let store = MemoryBackend::new(2);
+        store.set_entry("cats", b"meow".to_vec(), None);
+        store.set_entry("dogs", b"woof".to_vec(), None);
+        store.get_entry("cats");
+        store.set_entry("birds", b"tweet".to_vec(), None);
+
+        assert_eq!(store.get_entry("dogs"), None);
+        assert_eq!(store.get_entry("cats"), Some(b"meow".to_vec()));
+        assert_eq!(store.get_entry("birds"), Some(b"tweet".to_vec()));
+    }
+}
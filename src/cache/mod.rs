@@ -0,0 +1,859 @@
+use std::fmt::{Debug, Display};
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use tokio::sync::RwLock;
+
+use crate::config::{CacheBackend, CacheTtls, Config, RedisTopology};
+
+use self::flight::{Flight, SingleFlight};
+use self::stats::Stats;
+
+mod backend;
+mod cluster;
+mod codec;
+mod file;
+mod flight;
+mod memory;
+mod redis;
+mod stats;
+mod supervisor;
+
+pub use self::backend::{Backend, BackendError, NodeStatus};
+pub use self::cluster::ClusterBackend;
+pub use self::codec::Codec;
+pub use self::file::FileBackend;
+pub use self::memory::MemoryBackend;
+pub use self::redis::RedisBackend;
+pub use self::stats::CacheStats;
+
+/// How often expired scoped entries are swept and the remaining ones counted
+const SWEEP_INTERVAL: Duration = Duration::from_secs(3600);
+const SCOPE: &str = "scope";
+/// Keys were prefixed with `scope.` or the full type name before they were namespaced,
+/// the scoped ones were stored without a ttl and are never read or overwritten anymore
+const LEGACY_PREFIXES: &[&str] = &["scope.", "dinkelberg::"];
+/// How long another instance may hold the lock for fetching a value
+const LOCK_TTL: Duration = Duration::from_secs(5);
+const LOCK_POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// How long checking the backend's health may take, a backend this slow counts as unhealthy
+const HEALTH_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Types that can be stored in the cache
+pub trait Cacheable: Serialize + DeserializeOwned {
+    /// short and stable name of the type, used in cache keys and to configure its ttl
+    const KIND: &'static str;
+    /// bump this whenever the serialized form changes, so old entries are no longer read
+    const VERSION: u32;
+}
+
+/// The cache used throughout the bot, backed by whatever store is configured
+pub type DynBackend = Box<dyn Backend>;
+
+pub struct Cache<B: Backend = DynBackend> {
+    backend: Option<B>,
+    /// prefix for every key, so multiple bots can share one store
+    namespace: String,
+    ttls: CacheTtls,
+    codec: Codec,
+    flights: SingleFlight,
+    /// coordinate fetches with other bot instances through the backend
+    distributed_lock: bool,
+    /// the backend is configured but unreachable, so the supervisor took it out of use
+    suspended: bool,
+}
+
+/// Outcome of trying to take the lock other bot instances share
+enum Lock {
+    Acquired(String),
+    /// another instance held the lock, and we waited for it to finish
+    Waited,
+    Skipped,
+}
+
+#[derive(Serialize, Debug)]
+pub struct CacheStatus {
+    /// is true when a cache backend is configured and could be set up, even while it's suspended
+    enabled: bool,
+    /// is true when the cache is enabled and a connection can be retrieved
+    healthy: bool,
+    /// how long the backend took to respond to a ping, in milliseconds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    latency_ms: Option<f64>,
+    stats: CacheStats,
+    /// health of every server, when the backend is spread over more than one
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    nodes: Vec<NodeStatus>,
+}
+
+impl CacheStatus {
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub(crate) fn latency_ms(&self) -> Option<f64> {
+        self.latency_ms
+    }
+
+    pub(crate) fn stats(&self) -> &CacheStats {
+        &self.stats
+    }
+
+    pub(crate) fn nodes(&self) -> &[NodeStatus] {
+        &self.nodes
+    }
+}
+
+lazy_static! {
+    static ref CACHE_POOL: RwLock<Arc<Cache>> = RwLock::new(Arc::new(Cache::new()));
+}
+
+impl<B: Backend> Cache<B> {
+    pub fn with_backend(backend: B, namespace: &str, ttls: CacheTtls) -> Self {
+        Cache {
+            backend: Some(backend),
+            namespace: namespace.to_owned(),
+            ttls,
+            codec: Codec::default(),
+            flights: SingleFlight::default(),
+            distributed_lock: false,
+            suspended: false,
+        }
+    }
+
+    pub fn with_codec(mut self, codec: Codec) -> Self {
+        self.codec = codec;
+        self
+    }
+
+    pub fn with_distributed_lock(mut self, distributed_lock: bool) -> Self {
+        self.distributed_lock = distributed_lock;
+        self
+    }
+
+    fn cache_key<T: Cacheable>(&self, id: impl Display) -> String {
+        format!("{}:{}:v{}:{}", self.namespace, T::KIND, T::VERSION, id)
+    }
+
+    fn scoped_key<T: Cacheable>(&self, scope: impl Display) -> String {
+        format!(
+            "{}:{}:{}:{}:v{}",
+            self.namespace,
+            SCOPE,
+            scope,
+            T::KIND,
+            T::VERSION
+        )
+    }
+
+    fn scope_prefix(&self) -> String {
+        format!("{}:{}:", self.namespace, SCOPE)
+    }
+
+    /// fetch and deserialize the value stored under this key
+    /// values that can't be deserialized are stale and get removed
+    async fn read<T: DeserializeOwned>(&self, cache_key: &str) -> Result<Option<T>, BackendError> {
+        let backend = match self.backend.as_ref() {
+            Some(backend) => backend,
+            None => return Ok(None),
+        };
+
+        let res = match backend.get(cache_key).await {
+            Ok(Some(res)) => res,
+            Ok(None) => return Ok(None),
+            Err(err) => {
+                error!("unable to fetch {} from cache: {}", cache_key, err);
+                return Err(err);
+            }
+        };
+
+        match Codec::decode::<T>(&res) {
+            Ok(res) => Ok(Some(res)),
+            Err(err) => {
+                warn!("removing stale cache entry {}: {}", cache_key, err);
+                self.remove(cache_key).await;
+                Ok(None)
+            }
+        }
+    }
+
+    /// read a value and count the outcome, backend errors are counted apart from misses
+    async fn lookup<T: Cacheable>(&self, cache_key: &str) -> Option<T> {
+        let started = Instant::now();
+        let res = self.read::<T>(cache_key).await;
+        Stats::lookup(started.elapsed());
+
+        match res {
+            Ok(Some(res)) => {
+                Stats::cache_hit(T::KIND);
+                debug!("found {} in cache", cache_key);
+                Some(res)
+            }
+            Ok(None) => {
+                Stats::cache_miss(T::KIND);
+                None
+            }
+            Err(_) => {
+                Stats::cache_error(T::KIND);
+                None
+            }
+        }
+    }
+
+    /// serialize and store a value, it never expires if no ttl is given
+    async fn write<T: Cacheable>(&self, cache_key: &str, object: &T, ttl: Option<Duration>) {
+        let backend = match self.backend.as_ref() {
+            Some(backend) => backend,
+            None => return,
+        };
+
+        let encoded = match self.codec.encode(object) {
+            Ok(res) => res,
+            Err(err) => {
+                error!("unable to serialize object for cache {}", err);
+                return;
+            }
+        };
+
+        if let Err(err) = backend.set(cache_key, encoded, ttl).await {
+            error!("unable to store object in cache: {}", err);
+            Stats::cache_error(T::KIND);
+        }
+    }
+
+    pub async fn fetch<T: Cacheable, I: Display>(&self, id: I) -> Option<T> {
+        let cache_key = self.cache_key::<T>(id);
+
+        self.lookup(&cache_key).await
+    }
+
+    /// store an object, it expires after the ttl configured for its type unless one is given
+    pub async fn store<T: Cacheable, I: Display>(&self, object: &T, id: I, ttl: Option<Duration>) {
+        let cache_key = self.cache_key::<T>(id);
+        let ttl = ttl.unwrap_or_else(|| self.ttls.get(T::KIND));
+
+        self.write(&cache_key, object, Some(ttl)).await
+    }
+
+    /// return the cached value, or fetch and store it
+    /// concurrent calls for the same id wait for the first one to finish and share its result
+    pub async fn fetch_with<T, I, F, Fut, E>(&self, id: I, fetch: F) -> Result<T, E>
+    where
+        T: Cacheable + Clone + Send + 'static,
+        I: Display,
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+        E: Clone + Send + 'static,
+    {
+        if let Some(res) = self.fetch(&id).await {
+            return Ok(res);
+        }
+
+        let cache_key = self.cache_key::<T>(&id);
+        let flight = loop {
+            match self.flights.join::<Result<T, E>>(&cache_key) {
+                Flight::Leading(flight) => break flight,
+                // the channel closes without a result when the fetching task was cancelled
+                Flight::Waiting(mut landed) => {
+                    if let Ok(res) = landed.recv().await {
+                        return res;
+                    }
+                }
+            }
+        };
+
+        // a flight that landed since the lookup above might have stored it
+        if let Ok(Some(res)) = self.read::<T>(&cache_key).await {
+            flight.land(Ok(res.clone()));
+            return Ok(res);
+        }
+
+        let lock = self.lock(&cache_key).await;
+        let res = match lock {
+            Lock::Waited => match self.read(&cache_key).await {
+                Ok(Some(res)) => Ok(res),
+                _ => fetch().await,
+            },
+            _ => fetch().await,
+        };
+
+        if let Ok(res) = &res {
+            self.store(res, &id, None).await;
+        }
+
+        if let (Lock::Acquired(token), Some(backend)) = (lock, self.backend.as_ref()) {
+            if let Err(err) = backend.unlock(&Self::lock_key(&cache_key), &token).await {
+                error!("unable to release cache lock for {}: {}", cache_key, err);
+            }
+        }
+
+        flight.land(res.clone());
+        res
+    }
+
+    fn lock_key(cache_key: &str) -> String {
+        format!("{}:lock", cache_key)
+    }
+
+    /// take the lock shared with other instances, or wait a while for the instance holding it
+    async fn lock(&self, cache_key: &str) -> Lock {
+        let backend = match self.backend.as_ref() {
+            Some(backend) if self.distributed_lock => backend,
+            _ => return Lock::Skipped,
+        };
+
+        let token = rand::random::<u64>().to_string();
+        match backend
+            .lock(&Self::lock_key(cache_key), &token, LOCK_TTL)
+            .await
+        {
+            Ok(true) => return Lock::Acquired(token),
+            Ok(false) => {}
+            Err(err) => {
+                error!("unable to take cache lock for {}: {}", cache_key, err);
+                return Lock::Skipped;
+            }
+        }
+
+        let deadline = Instant::now() + LOCK_TTL;
+        while Instant::now() < deadline {
+            tokio::time::sleep(LOCK_POLL_INTERVAL).await;
+
+            if let Ok(Some(_)) = backend.get(cache_key).await {
+                break;
+            }
+        }
+
+        Lock::Waited
+    }
+
+    pub async fn fetch_scoped<T: Cacheable, S: Display>(&self, scope: S) -> Option<T> {
+        let cache_key = self.scoped_key::<T>(scope);
+
+        self.lookup(&cache_key).await
+    }
+
+    pub async fn store_scoped<T: Cacheable, S: Display>(&self, object: &T, scope: S) {
+        let cache_key = self.scoped_key::<T>(scope);
+
+        self.write(&cache_key, object, Some(self.ttls.scoped)).await
+    }
+
+    /// drop expired entries and count the remaining ones, returns all and scoped entries
+    pub async fn sweep(&self) -> Option<(usize, usize)> {
+        let backend = self.backend.as_ref()?;
+
+        match backend.keys(&format!("{}:", self.namespace)).await {
+            Ok(keys) => {
+                let scope_prefix = self.scope_prefix();
+                let scoped_keys = keys
+                    .iter()
+                    .filter(|key| key.starts_with(&scope_prefix))
+                    .count();
+
+                Some((keys.len(), scoped_keys))
+            }
+            Err(err) => {
+                error!("unable to sweep cache entries: {}", err);
+                None
+            }
+        }
+    }
+
+    fn kind_prefix(&self, kind: Option<&str>) -> String {
+        match kind {
+            Some(kind) => format!("{}:{}:", self.namespace, kind),
+            None => format!("{}:", self.namespace),
+        }
+    }
+
+    /// delete the entries stored under the keys used before they were namespaced
+    pub async fn remove_legacy(&self) -> Result<usize, BackendError> {
+        let backend = match self.backend.as_ref() {
+            Some(backend) => backend,
+            None => return Ok(0),
+        };
+
+        let mut deleted = 0;
+        for prefix in LEGACY_PREFIXES {
+            deleted += backend.delete_prefix(prefix).await?;
+        }
+
+        Ok(deleted)
+    }
+
+    /// delete every entry of a type, or all entries when no type is given, returns how many were deleted
+    pub async fn remove_all(&self, kind: Option<&str>) -> Result<usize, BackendError> {
+        let backend = match self.backend.as_ref() {
+            Some(backend) => backend,
+            None => return Ok(0),
+        };
+
+        backend.delete_prefix(&self.kind_prefix(kind)).await
+    }
+
+    pub async fn remove(&self, cache_key: &str) {
+        let backend = match self.backend.as_ref() {
+            Some(backend) => backend,
+            None => return,
+        };
+
+        if let Err(err) = backend.delete(cache_key).await {
+            error!("unable to delete object from cache: {}", err);
+        }
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.backend.is_some()
+    }
+
+    fn is_suspended(&self) -> bool {
+        self.suspended
+    }
+
+    /// check if the backend can be reached, a disabled cache has nothing to reach
+    async fn ping(&self) -> Result<(), BackendError> {
+        match self.backend.as_ref() {
+            Some(backend) => within_health_timeout(backend.ping()).await,
+            None => Ok(()),
+        }
+    }
+
+    pub async fn health(&self) -> CacheStatus {
+        let backend = match self.backend.as_ref() {
+            Some(backend) => backend,
+            // a suspended cache is still enabled, it's just not healthy
+            None => {
+                return CacheStatus {
+                    enabled: self.suspended,
+                    healthy: !self.suspended,
+                    latency_ms: None,
+                    stats: Stats::snapshot(),
+                    nodes: Vec::new(),
+                }
+            }
+        };
+
+        let started = Instant::now();
+        let latency_ms = match within_health_timeout(backend.ping()).await {
+            Ok(()) => Some(started.elapsed().as_secs_f64() * 1000.0),
+            Err(err) => {
+                error!("{} cache is unhealthy: {}", backend.name(), err);
+                None
+            }
+        };
+
+        let nodes = match within_health_timeout(backend.nodes()).await {
+            Ok(nodes) => nodes,
+            Err(err) => {
+                error!(
+                    "unable to check the {} cache nodes: {}",
+                    backend.name(),
+                    err
+                );
+                Vec::new()
+            }
+        };
+
+        CacheStatus {
+            enabled: true,
+            healthy: latency_ms.is_some(),
+            latency_ms,
+            stats: Stats::snapshot(),
+            nodes,
+        }
+    }
+}
+
+async fn within_health_timeout<T>(
+    check: impl Future<Output = Result<T, BackendError>>,
+) -> Result<T, BackendError> {
+    tokio::time::timeout(HEALTH_TIMEOUT, check)
+        .await
+        .unwrap_or_else(|_| Err(BackendError::Timeout(HEALTH_TIMEOUT)))
+}
+
+impl Cache {
+    fn default() -> Self {
+        Cache {
+            backend: None,
+            namespace: Config::bot_name().to_owned(),
+            ttls: Config::cache_ttls().clone(),
+            codec: Cache::codec(),
+            flights: SingleFlight::default(),
+            distributed_lock: false,
+            suspended: false,
+        }
+    }
+
+    /// create a new cache object, this ignores all errors to make sure the cache doesn't break the application
+    fn new() -> Self {
+        info!("creating cache pool");
+        match Cache::backend() {
+            Some(backend) => {
+                Cache::with_backend(backend, Config::bot_name(), Config::cache_ttls().clone())
+                    .with_codec(Cache::codec())
+                    .with_distributed_lock(Config::cache_distributed_lock())
+            }
+            None => Cache::default(),
+        }
+    }
+
+    fn codec() -> Codec {
+        Codec::new(Config::cache_format(), Config::cache_compress_above())
+    }
+
+    /// set up the configured backend
+    fn backend() -> Option<DynBackend> {
+        match Config::cache_backend() {
+            CacheBackend::Redis => match Config::redis_topology() {
+                Some(RedisTopology::Standalone(redis_url)) => match RedisBackend::new(redis_url) {
+                    Ok(backend) => Some(Box::new(backend)),
+                    Err(err) => {
+                        error!("unable to initiate cache pool: {}", err);
+                        None
+                    }
+                },
+                Some(RedisTopology::Sentinel { sentinels, master }) => {
+                    info!("using redis master `{}` through sentinels", master);
+                    Some(Box::new(RedisBackend::sentinel(
+                        &sentinels,
+                        master,
+                        Config::redis_url(),
+                    )))
+                }
+                Some(RedisTopology::Cluster(nodes)) => {
+                    info!("using the redis cluster at {}", nodes.join(", "));
+                    match ClusterBackend::new(&nodes) {
+                        Ok(backend) => Some(Box::new(backend)),
+                        Err(err) => {
+                            error!("unable to set up the redis cluster: {}", err);
+                            None
+                        }
+                    }
+                }
+                None => {
+                    info!("cache pool not initialising due to missing `REDIS_URL`");
+                    None
+                }
+            },
+            CacheBackend::Memory => {
+                info!("using the in-memory cache");
+                Some(Box::new(
+                    MemoryBackend::new(Config::cache_memory_capacity()),
+                ))
+            }
+            CacheBackend::File => {
+                info!("using the file cache in {}", Config::cache_file_path());
+                match FileBackend::new(Config::cache_file_path()) {
+                    Ok(backend) => Some(Box::new(backend)),
+                    Err(err) => {
+                        error!("unable to create the cache directory: {}", err);
+                        None
+                    }
+                }
+            }
+            CacheBackend::None => {
+                info!("cache is disabled through `CACHE_BACKEND`");
+                None
+            }
+        }
+    }
+
+    pub(crate) fn init() {
+        info!("initializing cache");
+        lazy_static::initialize(&CACHE_POOL);
+        tokio::spawn(Cache::sweeper());
+        tokio::spawn(supervisor::supervise());
+    }
+
+    /// periodically sweep the entries, so their amount can be reported
+    /// entries under legacy keys are removed on the first sweep the backend can be reached
+    async fn sweeper() {
+        let mut interval = tokio::time::interval(SWEEP_INTERVAL);
+        let mut legacy_removed = false;
+
+        loop {
+            interval.tick().await;
+            let cache = Cache::current().await;
+
+            if !legacy_removed && cache.is_enabled() {
+                match cache.remove_legacy().await {
+                    Ok(deleted) => {
+                        if deleted > 0 {
+                            info!("removed {} cache entries with legacy keys", deleted);
+                        }
+                        legacy_removed = true;
+                    }
+                    Err(err) => error!("unable to remove legacy cache entries: {}", err),
+                }
+            }
+
+            if let Some((keys, scoped_keys)) = cache.sweep().await {
+                info!("{} cache entries, {} scoped", keys, scoped_keys);
+                Stats::swept(keys, scoped_keys);
+            }
+        }
+    }
+
+    /// the cache currently in use, it's swapped out when the cache gets disabled or enabled
+    async fn current() -> Arc<Cache> {
+        CACHE_POOL.read().await.clone()
+    }
+
+    /// Fetch an item from the cache, or fetch it from its source and store it
+    /// Concurrent calls for the same item only fetch it once
+    #[tracing::instrument(name = "cache::get_or_fetch", skip(fetch))]
+    pub(crate) async fn get_or_fetch<T, I, F, Fut, E>(id: I, fetch: F) -> Result<T, E>
+    where
+        T: Cacheable + Clone + Send + 'static,
+        I: Display + Debug,
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+        E: Clone + Send + 'static,
+    {
+        Cache::current().await.fetch_with(id, fetch).await
+    }
+
+    /// Store an item in cache, scoped behind an identifier
+    #[tracing::instrument(name = "cache::set_scoped", skip(object))]
+    pub(crate) async fn set_scoped<T: Cacheable, S: Display + Debug>(object: &T, scope: S) {
+        Cache::current().await.store_scoped(object, scope).await
+    }
+
+    #[tracing::instrument(name = "cache::get_scoped")]
+    pub(crate) async fn get_scoped<T: Cacheable, S: Display + Debug>(scope: S) -> Option<T> {
+        Cache::current().await.fetch_scoped(scope).await
+    }
+
+    /// Delete every entry of a type, or all entries when no type is given
+    /// Scoped entries are flushed as the `scope` type
+    #[tracing::instrument(name = "cache::flush")]
+    pub(crate) async fn flush(kind: Option<&str>) -> Result<usize, BackendError> {
+        Cache::current().await.remove_all(kind).await
+    }
+
+    pub(crate) async fn disable_cache() {
+        let mut cache = CACHE_POOL.write().await;
+
+        *cache = Arc::new(Cache::default());
+    }
+
+    /// stop using the backend while it can't be reached, it's still reported as enabled
+    /// returns false when the checked cache was swapped out in the meantime, and is left alone
+    async fn suspend_cache(checked: &Arc<Cache>) -> bool {
+        let mut cache = CACHE_POOL.write().await;
+        if !Arc::ptr_eq(&cache, checked) {
+            return false;
+        }
+
+        *cache = Arc::new(Cache {
+            suspended: true,
+            ..Cache::default()
+        });
+        true
+    }
+
+    /// enable a suspended cache again once its backend can be reached
+    /// returns false when an admin enabled or disabled the cache in the meantime, and it's left alone
+    async fn resume_cache(suspended: &Arc<Cache>) -> Result<bool, BackendError> {
+        let resumed = Cache::new();
+        resumed.ping().await?;
+
+        let mut cache = CACHE_POOL.write().await;
+        if !Arc::ptr_eq(&cache, suspended) {
+            return Ok(false);
+        }
+
+        *cache = Arc::new(resumed);
+        Ok(true)
+    }
+
+    /// swap in a freshly configured cache, unless its backend can't be reached
+    /// returns false when there's no backend to enable, because none is configured or it can't be set up
+    pub(crate) async fn enable_cache() -> Result<bool, BackendError> {
+        let cache = Cache::new();
+        if !cache.is_enabled() {
+            return Ok(false);
+        }
+        cache.ping().await?;
+
+        *CACHE_POOL.write().await = Arc::new(cache);
+        Ok(true)
+    }
+
+    /// set up the configured backend and reach it, without using it as the cache
+    ///
+    /// Returns the name of the backend, or nothing when the cache is disabled or couldn't be set up.
+    pub(crate) async fn check() -> Result<Option<&'static str>, BackendError> {
+        let cache = Cache::new();
+        cache.ping().await?;
+
+        Ok(cache.backend.as_ref().map(|backend| backend.name()))
+    }
+
+    pub(crate) async fn status() -> CacheStatus {
+        Cache::current().await.health().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+    struct Kitten {
+        name: String,
+    }
+
+    impl Cacheable for Kitten {
+        const KIND: &'static str = "kitten";
+        const VERSION: u32 = 1;
+    }
+
+    #[derive(Serialize, Deserialize, Debug)]
+    struct KittenV2 {
+        name: String,
+        age: u8,
+    }
+
+    impl Cacheable for KittenV2 {
+        const KIND: &'static str = "kitten";
+        const VERSION: u32 = 1;
+    }
+
+    #[tokio::test]
+    async fn stores_values_in_the_backend() {
+        let cache = Cache::with_backend(MemoryBackend::new(10), "test", CacheTtls::default());
+        let kitten = Kitten {
+            name: String::from("Tom"),
+        };
+
+        assert_eq!(cache.fetch::<Kitten, _>("tom").await, None);
+
+        cache.store(&kitten, "tom", None).await;
+        cache.store_scoped(&kitten, 42).await;
+
+        assert_eq!(cache.fetch::<Kitten, _>("tom").await, Some(kitten));
+        assert!(cache.fetch_scoped::<Kitten, _>(42).await.is_some());
+        assert!(cache.fetch_scoped::<Kitten, _>(43).await.is_none());
+        assert_eq!(cache.sweep().await, Some((2, 1)));
+        assert_eq!(cache.remove_all(Some(Kitten::KIND)).await.unwrap(), 1);
+        assert_eq!(cache.sweep().await, Some((1, 1)));
+    }
+
+    #[test]
+    fn namespaces_keys() {
+        let cache = Cache::with_backend(MemoryBackend::new(10), "bot", CacheTtls::default());
+
+        assert_eq!(cache.cache_key::<Kitten>("tom"), "bot:kitten:v1:tom");
+        assert_eq!(cache.scoped_key::<Kitten>(42), "bot:scope:42:kitten:v1");
+    }
+
+    #[tokio::test]
+    async fn removes_entries_with_legacy_keys() {
+        let cache = Cache::with_backend(MemoryBackend::new(10), "test", CacheTtls::default());
+        let backend = cache.backend.as_ref().unwrap();
+        for key in &[
+            "scope.42.dinkelberg::ddg::ImageResponse",
+            "dinkelberg::ddg::WikiResponse.cat",
+        ] {
+            backend.set(key, Vec::new(), None).await.unwrap();
+        }
+        cache
+            .store_scoped(
+                &Kitten {
+                    name: String::from("Tom"),
+                },
+                42,
+            )
+            .await;
+
+        assert_eq!(cache.remove_legacy().await.unwrap(), 2);
+        assert_eq!(
+            backend.keys("").await.unwrap(),
+            vec![String::from("test:scope:42:kitten:v1")]
+        );
+    }
+
+    #[tokio::test]
+    async fn removes_entries_that_fail_to_decode() {
+        let cache = Cache::with_backend(MemoryBackend::new(10), "test", CacheTtls::default());
+        let kitten = Kitten {
+            name: String::from("Tom"),
+        };
+
+        cache.store(&kitten, "tom", None).await;
+
+        assert!(cache.fetch::<KittenV2, _>("tom").await.is_none());
+        assert_eq!(cache.fetch::<Kitten, _>("tom").await, None);
+    }
+
+    #[tokio::test]
+    async fn suspended_cache_is_enabled_but_unhealthy() {
+        let cache = Cache::<MemoryBackend> {
+            backend: None,
+            suspended: true,
+            ..Cache::with_backend(MemoryBackend::new(10), "test", CacheTtls::default())
+        };
+
+        let status = cache.health().await;
+        assert!(status.is_enabled());
+        assert!(!status.healthy);
+    }
+
+    #[tokio::test]
+    async fn concurrent_fetches_are_coalesced() {
+        let cache = Cache::with_backend(MemoryBackend::new(10), "test", CacheTtls::default());
+        let fetches = AtomicUsize::default();
+
+        let fetch = || async {
+            fetches.fetch_add(1, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            Ok::<_, ()>(Kitten {
+                name: String::from("Tom"),
+            })
+        };
+
+        let (first, second, third) = tokio::join!(
+            cache.fetch_with("tom", fetch),
+            cache.fetch_with("tom", fetch),
+            cache.fetch_with("tom", fetch),
+        );
+
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+        assert_eq!(first, second);
+        assert_eq!(second, third);
+    }
+
+    #[tokio::test]
+    async fn concurrent_fetches_share_their_result_without_a_backend() {
+        let cache = Cache::<MemoryBackend> {
+            backend: None,
+            ..Cache::with_backend(MemoryBackend::new(10), "test", CacheTtls::default())
+        };
+        let fetches = AtomicUsize::default();
+
+        let fetch = || async {
+            fetches.fetch_add(1, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            Err::<Kitten, _>("unreachable")
+        };
+
+        let (first, second, third) = tokio::join!(
+            cache.fetch_with("tom", fetch),
+            cache.fetch_with("tom", fetch),
+            cache.fetch_with("tom", fetch),
+        );
+
+        assert_eq!(fetches.load(Ordering::SeqCst), 1);
+        assert_eq!(first, Err("unreachable"));
+        assert_eq!(first, second);
+        assert_eq!(second, third);
+    }
+}
@@ -0,0 +1,385 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use deadpool_redis::{redis::cmd, ConnectionWrapper, Pool};
+use redis::{Cmd, ErrorKind, FromRedisValue, RedisError, RedisResult};
+use reqwest::Url;
+use tokio::sync::RwLock;
+
+use super::backend::{Backend, BackendError, NodeStatus};
+
+/// Amount of keys redis looks at for every `SCAN` iteration
+pub(super) const SCAN_COUNT: usize = 1000;
+
+lazy_static! {
+    /// only delete a lock if it's still ours, it might have expired and been taken by someone else
+    pub(super) static ref UNLOCK: redis::Script = redis::Script::new(
+        r#"if redis.call("GET", KEYS[1]) == ARGV[1] then return redis.call("DEL", KEYS[1]) else return 0 end"#
+    );
+}
+
+/// Where the redis master is found
+enum Source {
+    Url(String),
+    /// ask the sentinels for the current master, the url provides its credentials and database
+    Sentinel {
+        sentinels: Vec<String>,
+        master: String,
+        url: Option<String>,
+    },
+}
+
+pub struct RedisBackend {
+    source: Source,
+    /// the master's address and a pool of connections to it
+    /// with sentinels it's dropped once the master is unreachable, so it's discovered again
+    pool: RwLock<Option<(String, Pool)>>,
+}
+
+impl RedisBackend {
+    /// create a connection pool, connections are only made once they're needed
+    pub fn new(redis_url: &str) -> Result<Self, deadpool_redis::CreatePoolError> {
+        let pool = RedisBackend::create_pool(redis_url)?;
+
+        Ok(Self {
+            source: Source::Url(redis_url.to_owned()),
+            pool: RwLock::new(Some((address(redis_url), pool))),
+        })
+    }
+
+    /// find the master through the sentinels, it's only looked up once a connection is needed
+    pub fn sentinel(sentinels: &[&str], master: &str, redis_url: Option<&str>) -> Self {
+        Self {
+            source: Source::Sentinel {
+                sentinels: sentinels
+                    .iter()
+                    .map(|&sentinel| sentinel.to_owned())
+                    .collect(),
+                master: master.to_owned(),
+                url: redis_url.map(str::to_owned),
+            },
+            pool: RwLock::new(None),
+        }
+    }
+
+    fn create_pool(redis_url: &str) -> Result<Pool, deadpool_redis::CreatePoolError> {
+        let cfg = deadpool_redis::Config {
+            url: Some(redis_url.to_owned()),
+            // Should be removed in a PR...
+            connection: None,
+            ..Default::default()
+        };
+
+        cfg.create_pool()
+    }
+
+    /// the current pool, asking the sentinels for the master when there is none
+    async fn pool(&self) -> Result<Pool, BackendError> {
+        if let Some((_, pool)) = self.pool.read().await.as_ref() {
+            return Ok(pool.clone());
+        }
+
+        let mut current = self.pool.write().await;
+        // someone else might have discovered the master while we waited
+        if let Some((_, pool)) = current.as_ref() {
+            return Ok(pool.clone());
+        }
+
+        let url = match &self.source {
+            Source::Url(url) => url.clone(),
+            Source::Sentinel {
+                sentinels,
+                master,
+                url,
+            } => RedisBackend::discover_master(sentinels, master, url.as_deref()).await?,
+        };
+
+        let pool = RedisBackend::create_pool(&url)?;
+        *current = Some((address(&url), pool.clone()));
+
+        Ok(pool)
+    }
+
+    #[tracing::instrument(name = "cache::redis::discover_master", skip(sentinels, url))]
+    async fn discover_master(
+        sentinels: &[String],
+        master: &str,
+        url: Option<&str>,
+    ) -> Result<String, BackendError> {
+        for sentinel in sentinels {
+            match RedisBackend::ask_sentinel(sentinel, master).await {
+                Ok(Some((host, port))) => {
+                    info!("redis master `{}` is at {}:{}", master, host, port);
+                    return Ok(node_url(url, &host, port));
+                }
+                Ok(None) => warn!(
+                    "sentinel {} doesn't know master `{}`",
+                    address(sentinel),
+                    master
+                ),
+                Err(err) => warn!("unable to ask sentinel {}: {}", address(sentinel), err),
+            }
+        }
+
+        Err(BackendError::NoMaster(master.to_owned()))
+    }
+
+    async fn ask_sentinel(sentinel: &str, master: &str) -> RedisResult<Option<(String, u16)>> {
+        let mut conn = redis::Client::open(sentinel)?
+            .get_async_connection()
+            .await?;
+
+        cmd("SENTINEL")
+            .arg("get-master-addr-by-name")
+            .arg(master)
+            .query_async(&mut conn)
+            .await
+    }
+
+    /// the master might have failed over, forget it so the sentinels are asked again
+    async fn forget_master(&self) {
+        if let Source::Sentinel { .. } = self.source {
+            if let Some((address, _)) = self.pool.write().await.take() {
+                warn!("lost the redis master at {}", address);
+            }
+        }
+    }
+
+    #[tracing::instrument(name = "cache::redis::connection", skip(self))]
+    async fn connection(&self) -> Result<ConnectionWrapper, BackendError> {
+        match self.pool().await?.get().await {
+            Ok(conn) => Ok(conn),
+            Err(err) => {
+                self.forget_master().await;
+                Err(err.into())
+            }
+        }
+    }
+
+    async fn failed(&self, err: RedisError) -> BackendError {
+        if err.kind() == ErrorKind::ReadOnly || err.is_io_error() || err.is_connection_dropped() {
+            self.forget_master().await;
+        }
+        err.into()
+    }
+
+    async fn query<T: FromRedisValue>(&self, cmd: &Cmd) -> Result<T, BackendError> {
+        let mut conn = self.connection().await?;
+
+        match cmd.query_async(&mut conn).await {
+            Ok(res) => Ok(res),
+            Err(err) => Err(self.failed(err).await),
+        }
+    }
+}
+
+#[async_trait]
+impl Backend for RedisBackend {
+    fn name(&self) -> &'static str {
+        "redis"
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, BackendError> {
+        self.query(cmd("GET").arg(key)).await
+    }
+
+    async fn set(
+        &self,
+        key: &str,
+        value: Vec<u8>,
+        ttl: Option<Duration>,
+    ) -> Result<(), BackendError> {
+        match ttl {
+            Some(ttl) => {
+                self.query(cmd("SETEX").arg(key).arg(ttl.as_secs()).arg(value))
+                    .await
+            }
+            None => self.query(cmd("SET").arg(key).arg(value)).await,
+        }
+    }
+
+    async fn delete(&self, key: &str) -> Result<(), BackendError> {
+        self.query(cmd("DEL").arg(key)).await
+    }
+
+    async fn keys(&self, prefix: &str) -> Result<Vec<String>, BackendError> {
+        let pattern = format!("{}*", escape_pattern(prefix));
+
+        let mut keys = Vec::new();
+        let mut cursor: u64 = 0;
+        loop {
+            let (next, mut batch): (u64, Vec<String>) = self
+                .query(
+                    cmd("SCAN")
+                        .arg(cursor)
+                        .arg("MATCH")
+                        .arg(&pattern)
+                        .arg("COUNT")
+                        .arg(SCAN_COUNT),
+                )
+                .await?;
+
+            keys.append(&mut batch);
+
+            if next == 0 {
+                return Ok(keys);
+            }
+            cursor = next;
+        }
+    }
+
+    /// deletes every batch the scan returns right away, instead of collecting all keys first
+    async fn delete_prefix(&self, prefix: &str) -> Result<usize, BackendError> {
+        let pattern = format!("{}*", escape_pattern(prefix));
+
+        let mut deleted = 0;
+        let mut cursor: u64 = 0;
+        loop {
+            let (next, batch): (u64, Vec<String>) = self
+                .query(
+                    cmd("SCAN")
+                        .arg(cursor)
+                        .arg("MATCH")
+                        .arg(&pattern)
+                        .arg("COUNT")
+                        .arg(SCAN_COUNT),
+                )
+                .await?;
+
+            if !batch.is_empty() {
+                let unlinked: usize = self.query(cmd("UNLINK").arg(batch)).await?;
+                deleted += unlinked;
+            }
+
+            if next == 0 {
+                return Ok(deleted);
+            }
+            cursor = next;
+        }
+    }
+
+    async fn ping(&self) -> Result<(), BackendError> {
+        self.query(&cmd("PING")).await
+    }
+
+    async fn lock(&self, key: &str, token: &str, ttl: Duration) -> Result<bool, BackendError> {
+        let res: Option<String> = self
+            .query(
+                cmd("SET")
+                    .arg(key)
+                    .arg(token)
+                    .arg("NX")
+                    .arg("PX")
+                    .arg(ttl.as_millis() as u64),
+            )
+            .await?;
+
+        Ok(res.is_some())
+    }
+
+    async fn unlock(&self, key: &str, token: &str) -> Result<(), BackendError> {
+        let mut conn = self.connection().await?;
+
+        match UNLOCK
+            .key(key)
+            .arg(token)
+            .invoke_async::<_, ()>(&mut conn)
+            .await
+        {
+            Ok(()) => Ok(()),
+            Err(err) => Err(self.failed(err).await),
+        }
+    }
+
+    /// with sentinels, the health of every sentinel and of the master they point to
+    async fn nodes(&self) -> Result<Vec<NodeStatus>, BackendError> {
+        let sentinels = match &self.source {
+            Source::Url(_) => return Ok(Vec::new()),
+            Source::Sentinel { sentinels, .. } => sentinels,
+        };
+
+        let mut nodes = Vec::with_capacity(sentinels.len() + 1);
+        for sentinel in sentinels {
+            let healthy = match redis::Client::open(sentinel.as_str()) {
+                Ok(client) => match client.get_async_connection().await {
+                    Ok(mut conn) => cmd("PING").query_async::<_, ()>(&mut conn).await.is_ok(),
+                    Err(_) => false,
+                },
+                Err(_) => false,
+            };
+
+            nodes.push(NodeStatus {
+                address: address(sentinel),
+                role: "sentinel",
+                healthy,
+            });
+        }
+
+        let healthy = self.ping().await.is_ok();
+        if let Some((address, _)) = self.pool.read().await.as_ref() {
+            nodes.push(NodeStatus {
+                address: address.clone(),
+                role: "master",
+                healthy,
+            });
+        }
+
+        Ok(nodes)
+    }
+}
+
+/// the url of another server, with the credentials and database of the given url
+pub(super) fn node_url(url: Option<&str>, host: &str, port: u16) -> String {
+    let url = url.and_then(|url| {
+        let mut url = Url::parse(url).ok()?;
+        url.set_host(Some(host)).ok()?;
+        url.set_port(Some(port)).ok()?;
+        Some(url.to_string())
+    });
+
+    url.unwrap_or_else(|| format!("redis://{}:{}", host, port))
+}
+
+/// the host and port of a redis url, leaving out the credentials so it can be logged
+pub(super) fn address(url: &str) -> String {
+    match Url::parse(url) {
+        Ok(url) => format!(
+            "{}:{}",
+            url.host_str().unwrap_or_default(),
+            url.port().unwrap_or(6379)
+        ),
+        Err(_) => String::from("<invalid url>"),
+    }
+}
+
+/// escape the characters redis treats as a glob pattern
+pub(super) fn escape_pattern(prefix: &str) -> String {
+    let mut pattern = String::with_capacity(prefix.len());
+    for c in prefix.chars() {
+        if matches!(c, '*' | '?' | '[' | ']' | '\\') {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+    }
+    pattern
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_glob_characters() {
+        assert_eq!(escape_pattern("scope.1.Vec<[u8]>"), "scope.1.Vec<\\[u8\\]>");
+    }
+
+    #[test]
+    fn node_url_keeps_credentials() {
+        assert_eq!(
+            node_url(Some("redis://:secret@old-master:6379/2"), "10.0.0.5", 6380),
+            "redis://:secret@10.0.0.5:6380/2"
+        );
+        assert_eq!(node_url(None, "10.0.0.5", 6380), "redis://10.0.0.5:6380");
+        assert_eq!(address("redis://:secret@10.0.0.5/2"), "10.0.0.5:6379");
+    }
+}
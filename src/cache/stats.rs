@@ -0,0 +1,120 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::metrics::CACHE_LOOKUPS;
+
+lazy_static! {
+    static ref STATS: Stats = Stats::new();
+}
+
+/// Counted since the bot started, shared by every cache that's swapped in
+pub struct Stats {
+    cache_hit: AtomicUsize,
+    cache_miss: AtomicUsize,
+    /// lookups and writes the backend failed on, these aren't counted as misses
+    cache_error: AtomicUsize,
+    lookups: AtomicUsize,
+    /// total time spent on lookups, in microseconds
+    lookup_micros: AtomicU64,
+    kinds: Mutex<HashMap<&'static str, KindStats>>,
+    /// amount of keys found during the last sweep
+    keys: AtomicUsize,
+    /// amount of scoped entries found during the last sweep
+    scoped_keys: AtomicUsize,
+}
+
+/// Counts for a single cached type, by `Cacheable::KIND`
+#[derive(Serialize, Debug, Default, Clone, Copy, PartialEq)]
+pub struct KindStats {
+    pub hits: usize,
+    pub misses: usize,
+    pub errors: usize,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct CacheStats {
+    pub hits: usize,
+    pub misses: usize,
+    pub errors: usize,
+    /// average time a lookup took, in milliseconds
+    pub average_latency_ms: f64,
+    pub keys: usize,
+    pub scoped_keys: usize,
+    pub kinds: BTreeMap<&'static str, KindStats>,
+}
+
+impl Stats {
+    fn new() -> Self {
+        Self {
+            cache_hit: AtomicUsize::default(),
+            cache_miss: AtomicUsize::default(),
+            cache_error: AtomicUsize::default(),
+            lookups: AtomicUsize::default(),
+            lookup_micros: AtomicU64::default(),
+            kinds: Mutex::new(HashMap::new()),
+            keys: AtomicUsize::default(),
+            scoped_keys: AtomicUsize::default(),
+        }
+    }
+
+    fn kind(kind: &'static str, update: impl FnOnce(&mut KindStats)) {
+        let mut kinds = STATS.kinds.lock().expect("cache stats poisoned");
+        update(kinds.entry(kind).or_default());
+    }
+
+    pub(super) fn cache_hit(kind: &'static str) {
+        STATS.cache_hit.fetch_add(1, Ordering::Relaxed);
+        Stats::kind(kind, |stats| stats.hits += 1);
+        CACHE_LOOKUPS.with_label_values(&[kind, "hit"]).inc();
+    }
+
+    pub(super) fn cache_miss(kind: &'static str) {
+        STATS.cache_miss.fetch_add(1, Ordering::Relaxed);
+        Stats::kind(kind, |stats| stats.misses += 1);
+        CACHE_LOOKUPS.with_label_values(&[kind, "miss"]).inc();
+    }
+
+    pub(super) fn cache_error(kind: &'static str) {
+        STATS.cache_error.fetch_add(1, Ordering::Relaxed);
+        Stats::kind(kind, |stats| stats.errors += 1);
+        CACHE_LOOKUPS.with_label_values(&[kind, "error"]).inc();
+    }
+
+    pub(super) fn lookup(elapsed: Duration) {
+        STATS.lookups.fetch_add(1, Ordering::Relaxed);
+        STATS
+            .lookup_micros
+            .fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    pub(super) fn swept(keys: usize, scoped_keys: usize) {
+        STATS.keys.store(keys, Ordering::Relaxed);
+        STATS.scoped_keys.store(scoped_keys, Ordering::Relaxed);
+    }
+
+    pub(super) fn snapshot() -> CacheStats {
+        let lookups = STATS.lookups.load(Ordering::Relaxed);
+        let average_latency_ms = match lookups {
+            0 => 0.0,
+            lookups => STATS.lookup_micros.load(Ordering::Relaxed) as f64 / lookups as f64 / 1000.0,
+        };
+
+        CacheStats {
+            hits: STATS.cache_hit.load(Ordering::Relaxed),
+            misses: STATS.cache_miss.load(Ordering::Relaxed),
+            errors: STATS.cache_error.load(Ordering::Relaxed),
+            average_latency_ms,
+            keys: STATS.keys.load(Ordering::Relaxed),
+            scoped_keys: STATS.scoped_keys.load(Ordering::Relaxed),
+            kinds: STATS
+                .kinds
+                .lock()
+                .expect("cache stats poisoned")
+                .iter()
+                .map(|(&kind, &stats)| (kind, stats))
+                .collect(),
+        }
+    }
+}
@@ -0,0 +1,60 @@
+use std::time::Duration;
+
+use super::Cache;
+use crate::backoff::Backoff;
+
+/// How often the backend is checked while it's reachable
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+/// How long to wait before checking again after failed checks
+const BACKOFF: Backoff = Backoff::new(Duration::from_secs(5), Duration::from_secs(60 * 5));
+
+/// Keep an eye on the backend, the cache is suspended while it can't be reached
+/// so commands don't wait for and log every failing call, and enabled again once it's back
+///
+/// A cache that's disabled or enabled on purpose is left alone, also while it's being checked.
+pub(super) async fn supervise() {
+    let mut failures: u32 = 0;
+
+    loop {
+        let cache = Cache::current().await;
+
+        if cache.is_enabled() {
+            match cache.ping().await {
+                Ok(()) => failures = 0,
+                Err(err) => {
+                    if Cache::suspend_cache(&cache).await {
+                        failures = failures.saturating_add(1);
+                        warn!(
+                            "cache is unreachable, suspending it for {}s: {}",
+                            BACKOFF.after(failures).as_secs(),
+                            err
+                        );
+                    }
+                }
+            }
+        } else if cache.is_suspended() {
+            match Cache::resume_cache(&cache).await {
+                Ok(resumed) => {
+                    if resumed {
+                        info!("cache is reachable again, enabling it");
+                    }
+                    failures = 0;
+                }
+                Err(err) => {
+                    failures = failures.saturating_add(1);
+                    warn!(
+                        "cache is still unreachable, retrying in {}s: {}",
+                        BACKOFF.after(failures).as_secs(),
+                        err
+                    );
+                }
+            }
+        }
+
+        drop(cache);
+        match failures {
+            0 => tokio::time::sleep(CHECK_INTERVAL).await,
+            _ => tokio::time::sleep(BACKOFF.after(failures)).await,
+        }
+    }
+}
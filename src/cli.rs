@@ -1,9 +1,164 @@
+use std::path::PathBuf;
+
+use structopt::clap::AppSettings;
 use structopt::StructOpt;
+use teloxide::prelude::*;
+
+use crate::cache::Cache;
+use crate::commands::reminders;
+use crate::config::{CacheBackend, Config, LogFormat};
+use crate::{http, BOT};
 
 #[derive(Debug, StructOpt)]
 #[structopt(name = "Dinkelberg", about = "A neat Telegram bot")]
 pub struct Opt {
+    /// Read the settings from a TOML file, environment variables override its settings
+    #[structopt(long, parse(from_os_str), global = true)]
+    pub config: Option<PathBuf>,
+
+    /// Options for running the bot when no subcommand is given
+    #[structopt(flatten)]
+    pub run: RunOpt,
+
+    #[structopt(subcommand)]
+    pub subcommand: Option<Subcommand>,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct RunOpt {
+    /// Receive updates through a webhook instead of long polling
+    #[structopt(long)]
+    pub webhook: bool,
+
+    /// Write logs as text or json, overrides `LOG_FORMAT`
+    #[structopt(long, possible_values = &["text", "json"])]
+    pub log_format: Option<LogFormat>,
+}
+
+#[derive(Debug, StructOpt)]
+pub enum Subcommand {
+    /// Run the bot, this is the default
+    Run(RunOpt),
     /// Print the bot commands and exit
-    #[structopt(short, long)]
-    pub commands: bool,
+    Commands,
+    /// Validate the config, and check the cache and Telegram can be reached
+    CheckConfig,
+    /// Send a message to a chat
+    #[structopt(setting = AppSettings::AllowNegativeNumbers)]
+    Send {
+        /// Chat to send the message to, group chats have a negative id
+        chat_id: i64,
+        text: String,
+    },
+    /// Inspect the reminders saved on the last shutdown
+    Reminders(RemindersCommand),
+}
+
+#[derive(Debug, StructOpt)]
+pub enum RemindersCommand {
+    /// List the saved reminders
+    List,
+    /// Delete the saved reminders, so they're never sent
+    Purge,
+}
+
+/// the config is valid by now, so check the services the bot needs without starting it
+pub(crate) async fn check_config() -> bool {
+    println!("Config: valid");
+
+    let cache = match Cache::check().await {
+        Ok(Some(backend)) => {
+            println!("Cache: {} reachable", backend);
+            true
+        }
+        Ok(None) if Config::cache_backend() == CacheBackend::None => {
+            println!("Cache: disabled");
+            true
+        }
+        Ok(None) => {
+            println!(
+                "Cache: unable to set up the {:?} cache",
+                Config::cache_backend()
+            );
+            false
+        }
+        Err(err) => {
+            println!("Cache: unreachable, {}", err);
+            false
+        }
+    };
+
+    // the bot panics when it's created without a token
+    if std::env::var_os("TELOXIDE_TOKEN").is_none() {
+        println!("Telegram: `TELOXIDE_TOKEN` is required");
+        return false;
+    }
+
+    let telegram = match BOT.get_me().await {
+        Ok(me) => {
+            let username = me.user.username.unwrap_or_default();
+            println!("Telegram: authorized as @{}", username);
+            if username != Config::bot_name() {
+                println!(
+                    "Telegram: `BOT_NAME` is `{}`, commands addressed to @{} are ignored",
+                    Config::bot_name(),
+                    username
+                );
+            }
+            true
+        }
+        Err(err) => {
+            println!("Telegram: {}", http::telegram_error(&err));
+            false
+        }
+    };
+
+    cache && telegram
+}
+
+pub(crate) async fn send(chat_id: i64, text: String) -> bool {
+    // the bot panics when it's created without a token
+    if std::env::var_os("TELOXIDE_TOKEN").is_none() {
+        eprintln!("`TELOXIDE_TOKEN` is required to send messages");
+        return false;
+    }
+
+    match BOT.send_message(chat_id, text).await {
+        Ok(message) => {
+            println!("Sent message {} to chat {}", message.id, chat_id);
+            true
+        }
+        Err(err) => {
+            eprintln!("Unable to send the message: {}", http::telegram_error(&err));
+            false
+        }
+    }
+}
+
+pub(crate) async fn reminders(command: RemindersCommand) -> bool {
+    let res = match command {
+        RemindersCommand::List => reminders::saved().await.map(|reminders| {
+            if reminders.is_empty() {
+                println!("No saved reminders");
+            }
+            for reminder in reminders {
+                println!("{}", reminder);
+            }
+        }),
+        RemindersCommand::Purge => reminders::purge()
+            .await
+            .map(|count| println!("Purged {} saved reminders", count)),
+    };
+
+    match res {
+        Ok(()) => true,
+        Err(err) => {
+            eprintln!(
+                "Unable to read the saved reminders in `{}`: {}",
+                Config::reminders_path(),
+                err
+            );
+            false
+        }
+    }
 }
@@ -0,0 +1,58 @@
+use teloxide::prelude::*;
+use teloxide::types::ParseMode;
+use teloxide::RequestError;
+
+use crate::cache::Cache;
+use crate::commands::health::{self, Table};
+use crate::commands::Context;
+use crate::config::Config;
+
+const USAGE: &str = "Usage: /cache stats | flush <type|all> | disable | enable";
+
+#[tracing::instrument(name = "commands::cache::manage", skip(cx))]
+pub(crate) async fn manage(cx: &Context, args: &str) -> anyhow::Result<Message, RequestError> {
+    let is_admin =
+        matches!(cx.update.from(), Some(user) if Config::admin_users().contains(&user.id));
+    if !is_admin {
+        return cx.reply_to("Only admins can manage the cache 🙅").await;
+    }
+
+    let mut args = args.split_whitespace();
+    let resp = match (args.next(), args.next(), args.next()) {
+        (Some("stats"), None, None) => {
+            let mut table = Table::default();
+            health::lookups(&mut table, Cache::status().await.stats());
+
+            return cx
+                .reply_to(table.render())
+                .parse_mode(ParseMode::Html)
+                .await;
+        }
+        (Some("flush"), Some(kind), None) => {
+            let kind = if kind == "all" { None } else { Some(kind) };
+            match Cache::flush(kind).await {
+                Ok(flushed) => format!("Flushed {} cache entries", flushed),
+                Err(err) => {
+                    error!("unable to flush the cache: {}", err);
+                    format!("Unable to flush the cache: {}", err)
+                }
+            }
+        }
+        (Some("disable"), None, None) => {
+            warn!("cache disabled by an admin");
+            Cache::disable_cache().await;
+            String::from("Cache disabled")
+        }
+        (Some("enable"), None, None) => match Cache::enable_cache().await {
+            Ok(true) => {
+                info!("cache enabled by an admin");
+                String::from("Cache enabled")
+            }
+            Ok(false) => String::from("No cache backend is configured, check `CACHE_BACKEND`"),
+            Err(err) => format!("Unable to enable the cache: {}", err),
+        },
+        _ => String::from(USAGE),
+    };
+
+    cx.reply_to(resp).await
+}
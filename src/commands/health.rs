@@ -1,18 +1,173 @@
+use std::time::Duration;
+
+use chrono::Utc;
 use teloxide::prelude::*;
+use teloxide::types::ParseMode;
+use teloxide::utils::html;
 use teloxide::RequestError;
 
-use crate::cache::Cache;
-use crate::commands::Context;
+use crate::cache::{Cache, CacheStats};
+use crate::commands::{self, reminders, Context};
+use crate::ddg::{self, CircuitState};
+
+/// Rows of labels and values, rendered as a monospaced table
+#[derive(Default)]
+pub(super) struct Table {
+    rows: Vec<(String, String)>,
+}
+
+impl Table {
+    pub(super) fn row(&mut self, label: &str, value: impl Into<String>) {
+        self.rows.push((label.to_owned(), value.into()));
+    }
+
+    /// render as html, so labels and values line up
+    pub(super) fn render(&self) -> String {
+        let width = self
+            .rows
+            .iter()
+            .map(|(label, _)| label.chars().count())
+            .max()
+            .unwrap_or_default();
+
+        let rows: Vec<String> = self
+            .rows
+            .iter()
+            .map(|(label, value)| format!("{:width$} │ {}", label, value, width = width))
+            .collect();
+
+        format!("<pre>{}</pre>", html::escape(&rows.join("\n")))
+    }
+}
 
 #[tracing::instrument(name = "commands::health::status", skip(cx))]
 pub(crate) async fn status(cx: &Context) -> anyhow::Result<Message, RequestError> {
-    let resp: String;
+    let (status, ddg_ping) = tokio::join!(Cache::status(), ddg::ping());
+    let mut table = Table::default();
+
+    let cache = match (status.is_enabled(), status.latency_ms()) {
+        (false, _) => String::from("disabled"),
+        (true, Some(latency_ms)) => format!("enabled, healthy ({:.1}ms)", latency_ms),
+        (true, None) => String::from("enabled, unhealthy"),
+    };
+    table.row("Cache", cache);
+    // the node addresses are internal, they're only reported over http
+    if !status.nodes().is_empty() {
+        let healthy = status.nodes().iter().filter(|node| node.healthy).count();
+        table.row(
+            "",
+            format!("{} of {} nodes healthy", healthy, status.nodes().len()),
+        );
+    }
+    lookups(&mut table, status.stats());
+
+    let reachability = match ddg_ping {
+        Ok(latency) => format!("reachable ({}ms)", latency.as_millis()),
+        Err(err) => {
+            warn!("DuckDuckGo is unreachable: {}", err);
+            String::from("unreachable")
+        }
+    };
+    let circuit = match ddg::circuit_state() {
+        CircuitState::Closed => String::from("available"),
+        CircuitState::Open { remaining_secs, .. } => {
+            format!("cooling down for {}s", remaining_secs)
+        }
+    };
+    table.row("DDG", format!("{}, {}", reachability, circuit));
+
+    let tokens = ddg::token_stats();
+    table.row(
+        "DDG tokens",
+        format!("{} reused, {} fetched", tokens.hits, tokens.misses),
+    );
+
+    if let Some(error) = ddg::last_error() {
+        let ago = Utc::now().signed_duration_since(error.at);
+        let status = error
+            .status
+            .map(|status| format!(" ({})", status.as_u16()))
+            .unwrap_or_default();
+        table.row(
+            "DDG error",
+            format!("{}m ago: {}{}", ago.num_minutes(), error.outcome, status),
+        );
+    }
+
+    table.row("Uptime", uptime(crate::STARTED.elapsed()));
+    table.row(
+        "Version",
+        format!("{} ({})", env!("CARGO_PKG_VERSION"), env!("GIT_HASH")),
+    );
+    table.row("Reminders", format!("{} pending", reminders::pending()));
+
+    let counts: Vec<String> = commands::command_counts()
+        .into_iter()
+        .map(|(name, count)| format!("{} {}", name, count))
+        .collect();
+    table.row("Commands", counts.join(", "));
+
+    cx.reply_to(table.render())
+        .parse_mode(ParseMode::Html)
+        .await
+}
 
-    if Cache::status().await.is_healthy() {
-        resp = String::from("Cache: healthy");
-    } else {
-        resp = String::from("Cache: unhealthy");
+/// add the cache entries and lookups so far, in total and per cached type
+pub(super) fn lookups(table: &mut Table, stats: &CacheStats) {
+    table.row(
+        "Entries",
+        format!("{} ({} scoped)", stats.keys, stats.scoped_keys),
+    );
+    table.row(
+        "Lookups",
+        format!(
+            "{} hits, {} misses, {} errors, {:.1}ms avg",
+            stats.hits, stats.misses, stats.errors, stats.average_latency_ms
+        ),
+    );
+    for (kind, kind_stats) in &stats.kinds {
+        table.row(
+            "",
+            format!(
+                "{}: {} hits, {} misses, {} errors",
+                kind, kind_stats.hits, kind_stats.misses, kind_stats.errors
+            ),
+        );
     }
+}
 
-    cx.reply_to(resp).await
+/// formatted as `2d 3h 4m`
+fn uptime(uptime: Duration) -> String {
+    let minutes = uptime.as_secs() / 60;
+    let (days, hours, minutes) = (minutes / (60 * 24), minutes / 60 % 24, minutes % 60);
+
+    match (days, hours) {
+        (0, 0) => format!("{}m", minutes),
+        (0, _) => format!("{}h {}m", hours, minutes),
+        _ => format!("{}d {}h {}m", days, hours, minutes),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_uptime() {
+        assert_eq!(uptime(Duration::from_secs(59)), "0m");
+        assert_eq!(uptime(Duration::from_secs(3 * 3600 + 120)), "3h 2m");
+        assert_eq!(uptime(Duration::from_secs(2 * 86400 + 60)), "2d 0h 1m");
+    }
+
+    #[test]
+    fn aligns_table_rows() {
+        let mut table = Table::default();
+        table.row("Cache", "disabled");
+        table.row("Uptime", "<1m");
+
+        assert_eq!(
+            table.render(),
+            "<pre>Cache  │ disabled\nUptime │ &lt;1m</pre>"
+        );
+    }
 }
@@ -14,7 +14,17 @@ pub(crate) async fn image(cx: &Context, query: &str) -> anyhow::Result<Message,
             .map_err(|e| e.into());
     }
 
-    let images = ddg::Client::search_images(query).await?;
+    let images = match ddg::Client::search_images(query).await {
+        Ok(images) => images,
+        Err(err) if err.is_throttled() => {
+            warn!("DuckDuckGo error: {}", err);
+            return cx
+                .reply_to("Search is cooling down, try again in a bit 🥶")
+                .await
+                .map_err(|e| e.into());
+        }
+        Err(err) => return Err(err.into()),
+    };
 
     Cache::set_scoped(&images, cx.chat_id()).await;
 
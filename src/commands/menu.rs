@@ -0,0 +1,174 @@
+use std::iter;
+
+use teloxide::prelude::*;
+use teloxide::types::{BotCommand, BotCommandScope, ChatId};
+use teloxide::utils::command::BotCommand as _;
+use teloxide::RequestError;
+
+use crate::commands::Command;
+use crate::config::Config;
+use crate::{http, BOT};
+
+/// Who a command is offered to in the command menu, every command can be used anywhere though
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Audience {
+    Everyone,
+    PrivateChats,
+    /// the users in `ADMIN_USERS`, in their private chat with the bot
+    Admins,
+}
+
+/// Descriptions of the commands by language, commands that aren't translated use the english ones
+const TRANSLATIONS: &[(&str, &[(&str, &str)])] = &[(
+    "nl",
+    &[
+        ("help", "Toon deze tekst"),
+        ("img", "Zoek een afbeelding"),
+        ("more", "Zoek meer afbeeldingen"),
+        ("health", "Bekijk hoe het met de bot gaat"),
+        ("cache", "Beheer de cache (enkel voor admins)"),
+        ("bodegem", "Een plaats die echt bestaat"),
+        ("remindme", "Herinner me na een bepaalde tijd"),
+        ("what", "Zoek op wat iets is"),
+        ("roll", "Prijs Kek"),
+    ],
+)];
+
+fn audience(command: &str) -> Audience {
+    match command {
+        "cache" => Audience::Admins,
+        // the status table is too much for group chats
+        "health" => Audience::PrivateChats,
+        _ => Audience::Everyone,
+    }
+}
+
+/// Register the commands with Telegram, so they show up in the command menu
+pub(crate) async fn register() {
+    let commands: Vec<(String, String)> = parse_descriptions(&Command::descriptions())
+        .into_iter()
+        .filter(|(command, _)| !Config::commands_disabled().contains(command))
+        .collect();
+
+    let languages =
+        iter::once(None).chain(TRANSLATIONS.iter().map(|(language, _)| Some(*language)));
+    for language in languages {
+        let scopes = iter::once((
+            BotCommandScope::AllPrivateChats,
+            menu(&commands, language, |audience| audience != Audience::Admins),
+        ))
+        .chain(iter::once((
+            BotCommandScope::AllGroupChats,
+            menu(&commands, language, |audience| {
+                audience == Audience::Everyone
+            }),
+        )))
+        .chain(Config::admin_users().iter().map(|&user| {
+            (
+                BotCommandScope::Chat(ChatId::Id(user)),
+                menu(&commands, language, |_| true),
+            )
+        }));
+
+        for (scope, menu) in scopes {
+            let mut request = BOT.set_my_commands(menu).scope(scope.clone());
+            if let Some(language) = language {
+                request = request.language_code(language);
+            }
+
+            match request.await {
+                Ok(_) => {}
+                // the other scopes won't get through either
+                Err(err @ RequestError::NetworkError(_)) => {
+                    error!(
+                        "unable to register the commands: {}",
+                        http::telegram_error(&err)
+                    );
+                    return;
+                }
+                Err(err) => warn!(
+                    "unable to register the commands for {:?}: {}",
+                    scope,
+                    http::telegram_error(&err)
+                ),
+            }
+        }
+    }
+
+    info!("registered the commands with Telegram");
+}
+
+/// the commands for an audience, described in a language
+fn menu(
+    commands: &[(String, String)],
+    language: Option<&str>,
+    include: impl Fn(Audience) -> bool,
+) -> Vec<BotCommand> {
+    let translations = TRANSLATIONS
+        .iter()
+        .find(|(translation, _)| Some(*translation) == language)
+        .map(|(_, descriptions)| *descriptions)
+        .unwrap_or_default();
+
+    commands
+        .iter()
+        .filter(|(command, _)| include(audience(command)))
+        .map(|(command, description)| {
+            let description = translations
+                .iter()
+                .find(|(translated, _)| translated == command)
+                .map(|(_, description)| *description)
+                .unwrap_or(description);
+            BotCommand::new(command, description)
+        })
+        .collect()
+}
+
+/// the commands and their descriptions, from lines like `/img - Fetch an image`
+pub(super) fn parse_descriptions(descriptions: &str) -> Vec<(String, String)> {
+    descriptions
+        .lines()
+        .filter_map(|line| {
+            let (command, description) = line.strip_prefix('/')?.split_once(" - ")?;
+            Some((command.trim().to_owned(), description.trim().to_owned()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_command_descriptions() {
+        assert_eq!(
+            parse_descriptions(
+                "These commands are supported:\n/help - display this text.\n/img - Fetch an image"
+            ),
+            vec![
+                (String::from("help"), String::from("display this text.")),
+                (String::from("img"), String::from("Fetch an image")),
+            ]
+        );
+    }
+
+    #[test]
+    fn builds_menus_per_audience_and_language() {
+        let commands = vec![
+            (String::from("img"), String::from("Fetch an image")),
+            (String::from("cache"), String::from("Manage the cache")),
+        ];
+
+        assert_eq!(
+            menu(&commands, None, |audience| audience == Audience::Everyone),
+            vec![BotCommand::new("img", "Fetch an image")]
+        );
+        assert_eq!(
+            menu(&commands, Some("nl"), |_| true),
+            vec![
+                BotCommand::new("img", "Zoek een afbeelding"),
+                BotCommand::new("cache", "Beheer de cache (enkel voor admins)"),
+            ]
+        );
+    }
+}
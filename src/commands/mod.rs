@@ -1,11 +1,18 @@
-use std::sync::Arc;
+use prometheus::core::Collector;
 
 use teloxide::prelude::*;
 use teloxide::utils::command::BotCommand;
+use teloxide::RequestError;
 
+use crate::config::Config;
+use crate::metrics::{self, COMMANDS, COMMAND_DURATION};
+use crate::telemetry;
+
+mod cache;
 mod health;
 mod img;
-mod reminders;
+pub(crate) mod menu;
+pub(crate) mod reminders;
 mod roll;
 mod what;
 
@@ -22,6 +29,8 @@ pub enum Command {
     More,
     #[command(description = "Get the bot's health status")]
     Health,
+    #[command(description = "Manage the cache (admins only)")]
+    Cache(String),
     #[command(description = "A place that is real and exists")]
     Bodegem,
     #[command(description = "Remind me in a given time")]
@@ -32,7 +41,50 @@ pub enum Command {
     Roll,
 }
 
-#[tracing::instrument(skip(cx))]
+impl Command {
+    /// the command as it's typed, without arguments
+    fn name(&self) -> &'static str {
+        match self {
+            Command::Help => "help",
+            Command::Img(_) => "img",
+            Command::More => "more",
+            Command::Health => "health",
+            Command::Cache(_) => "cache",
+            Command::Bodegem => "bodegem",
+            Command::RemindMe(_) => "remindme",
+            Command::What(_) => "what",
+            Command::Roll => "roll",
+        }
+    }
+}
+
+/// the names of every command, as they're typed
+pub(crate) fn names() -> Vec<String> {
+    menu::parse_descriptions(&Command::descriptions())
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect()
+}
+
+/// how often each command was used since the bot started, sorted by name
+pub(crate) fn command_counts() -> Vec<(String, u64)> {
+    let mut counts: Vec<_> = COMMANDS
+        .collect()
+        .iter()
+        .flat_map(|family| family.get_metric())
+        .filter_map(|metric| {
+            let name = metric.get_label().first()?.get_value().to_owned();
+            Some((name, metric.get_counter().get_value() as u64))
+        })
+        .collect();
+    counts.sort_unstable();
+    counts
+}
+
+#[tracing::instrument(
+    skip(cx, command),
+    fields(chat_id = cx.chat_id(), command = command.name(), trace_id = tracing::field::Empty)
+)]
 pub(crate) async fn responder(
     cx: UpdateWithCx<AutoSend<Bot>, Message>,
     command: Command,
@@ -42,6 +94,32 @@ pub(crate) async fn responder(
         command,
         cx.chat_id()
     );
+    telemetry::record_trace_id(&tracing::Span::current());
+    let name = command.name();
+    if Config::commands_disabled()
+        .iter()
+        .any(|disabled| disabled == name)
+    {
+        debug!("Ignoring disabled command `{}`", name);
+        return Ok(());
+    }
+
+    COMMANDS.with_label_values(&[name]).inc();
+    let _timer = COMMAND_DURATION.with_label_values(&[name]).start_timer();
+
+    let res = handle(cx, command).await;
+    if let Some(err) = res
+        .as_ref()
+        .err()
+        .and_then(|err| err.downcast_ref::<RequestError>())
+    {
+        metrics::telegram_error(name, err);
+    }
+
+    res
+}
+
+async fn handle(cx: Context, command: Command) -> anyhow::Result<(), anyhow::Error> {
     match command {
         Command::Help => {
             cx.answer(Command::descriptions()).send().await?;
@@ -55,11 +133,14 @@ pub(crate) async fn responder(
         Command::Health => {
             health::status(&cx).await?;
         }
+        Command::Cache(args) => {
+            cache::manage(&cx, &args).await?;
+        }
         Command::Bodegem => {
             cx.answer_location(50.8614773, 4.211304).await?;
         }
         Command::RemindMe(query) => {
-            reminders::remind_me(Arc::new(cx), query).await?;
+            reminders::remind_me(&cx, query).await?;
         }
         Command::What(query) => {
             what::what(&cx, &query).await?;
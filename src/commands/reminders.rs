@@ -1,14 +1,43 @@
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
 
-use chrono::{Local, NaiveDateTime};
+use chrono::{DateTime, Local, NaiveDateTime, Utc};
 use date_time_parser::{DateParser, TimeParser};
 use teloxide::prelude::*;
 use teloxide::RequestError;
 
 use crate::commands::Context;
+use crate::config::Config;
+use crate::metrics::{self, PENDING_REMINDERS};
+use crate::BOT;
+
+lazy_static! {
+    /// reminders waiting for their deadline, so they can be saved on shutdown
+    static ref SCHEDULED: Mutex<HashMap<u64, Reminder>> = Mutex::new(HashMap::new());
+}
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+/// A reminder, replied to the message that asked for it
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub(crate) struct Reminder {
+    chat_id: i64,
+    message_id: i32,
+    text: String,
+    deadline: DateTime<Utc>,
+}
+
+/// reminders that are waiting for their deadline
+pub(crate) fn pending() -> usize {
+    PENDING_REMINDERS.get() as usize
+}
 
 #[tracing::instrument(name = "commands::remind_me", skip(cx))]
-pub(crate) async fn remind_me(cx: Arc<Context>, query: String) -> anyhow::Result<(), RequestError> {
+pub(crate) async fn remind_me(cx: &Context, query: String) -> anyhow::Result<(), RequestError> {
     let date = DateParser::parse(&query);
     let time = TimeParser::parse(&query);
 
@@ -38,13 +67,11 @@ pub(crate) async fn remind_me(cx: Arc<Context>, query: String) -> anyhow::Result
 
     debug!("Reminder created with deadline: {:?}", deadline);
 
-    let respond_cx = cx.clone();
-    tokio::task::spawn(async move {
-        tokio::time::sleep(deadline.to_std().unwrap_or_default()).await;
-
-        if let Err(e) = respond_cx.reply_to(query).send().await {
-            error!("Reminder failure: {}", e);
-        }
+    schedule(Reminder {
+        chat_id: cx.chat_id(),
+        message_id: cx.update.id,
+        text: query,
+        deadline: Utc::now() + deadline,
     });
 
     cx.reply_to(format!(
@@ -55,3 +82,131 @@ pub(crate) async fn remind_me(cx: Arc<Context>, query: String) -> anyhow::Result
 
     Ok(())
 }
+
+fn schedule(reminder: Reminder) {
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    let delay = reminder
+        .deadline
+        .signed_duration_since(Utc::now())
+        .to_std()
+        .unwrap_or_default();
+
+    SCHEDULED
+        .lock()
+        .expect("reminders poisoned")
+        .insert(id, reminder.clone());
+    PENDING_REMINDERS.inc();
+
+    tokio::task::spawn(async move {
+        tokio::time::sleep(delay).await;
+        SCHEDULED.lock().expect("reminders poisoned").remove(&id);
+        PENDING_REMINDERS.dec();
+
+        if let Err(e) = BOT
+            .send_message(reminder.chat_id, reminder.text)
+            .reply_to_message_id(reminder.message_id)
+            .send()
+            .await
+        {
+            error!("Reminder failure: {}", e);
+            metrics::telegram_error("remindme", &e);
+        }
+    });
+}
+
+impl fmt::Display for Reminder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} in chat {}: {}",
+            self.deadline.to_rfc3339(),
+            self.chat_id,
+            self.text
+        )
+    }
+}
+
+/// save the pending reminders, their tasks don't survive a restart
+pub(crate) async fn persist() -> io::Result<usize> {
+    let reminders: Vec<Reminder> = SCHEDULED
+        .lock()
+        .expect("reminders poisoned")
+        .values()
+        .cloned()
+        .collect();
+    if reminders.is_empty() {
+        return Ok(0);
+    }
+
+    save(Path::new(Config::reminders_path()), &reminders).await?;
+    Ok(reminders.len())
+}
+
+/// schedule the reminders saved on the last shutdown, ones that are overdue are sent right away
+pub(crate) async fn restore() -> io::Result<usize> {
+    let path = Path::new(Config::reminders_path());
+    let reminders = match load(path).await {
+        Ok(reminders) => reminders,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(0),
+        Err(err) => return Err(err),
+    };
+
+    // they're saved again on the next shutdown, when they haven't been sent by then
+    tokio::fs::remove_file(path).await?;
+
+    let count = reminders.len();
+    reminders.into_iter().for_each(schedule);
+    Ok(count)
+}
+
+/// the reminders saved on the last shutdown, sorted by deadline
+pub(crate) async fn saved() -> io::Result<Vec<Reminder>> {
+    let mut reminders = match load(Path::new(Config::reminders_path())).await {
+        Ok(reminders) => reminders,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Vec::new(),
+        Err(err) => return Err(err),
+    };
+
+    reminders.sort_by_key(|reminder| reminder.deadline);
+    Ok(reminders)
+}
+
+/// drop the reminders saved on the last shutdown, so they're never sent
+pub(crate) async fn purge() -> io::Result<usize> {
+    let count = saved().await?.len();
+
+    match tokio::fs::remove_file(Config::reminders_path()).await {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+        _ => Ok(count),
+    }
+}
+
+async fn save(path: &Path, reminders: &[Reminder]) -> io::Result<()> {
+    tokio::fs::write(path, serde_json::to_vec(reminders)?).await
+}
+
+async fn load(path: &Path) -> io::Result<Vec<Reminder>> {
+    Ok(serde_json::from_slice(&tokio::fs::read(path).await?)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn saves_and_loads_reminders() {
+        let path =
+            std::env::temp_dir().join(format!("dinkelberg-reminders-{}", rand::random::<u64>()));
+        let reminders = vec![Reminder {
+            chat_id: -42,
+            message_id: 7,
+            text: String::from("feed the cat"),
+            deadline: Utc::now(),
+        }];
+
+        save(&path, &reminders).await.unwrap();
+        assert_eq!(load(&path).await.unwrap(), reminders);
+
+        tokio::fs::remove_file(&path).await.unwrap();
+    }
+}
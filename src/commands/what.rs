@@ -11,8 +11,13 @@ pub(crate) async fn what(cx: &Context, query: &str) -> anyhow::Result<Message, E
         Err(err) => {
             if matches!(err, DuckDuckGoError::EmptyResponse) {
                 cx.reply_to("I don't know 🤔").await.map_err(|e| e.into())
+            } else if err.is_throttled() {
+                log::warn!("DuckDuckGo error: {}", err);
+                cx.reply_to("Search is cooling down, try again in a bit 🥶")
+                    .await
+                    .map_err(|e| e.into())
             } else {
-                log::error!("DuckDuckGo error: {}", err);
+                // the failure is already logged by the ddg client
                 cx.reply_to("Something went wrong...")
                     .await
                     .map_err(|e| e.into())
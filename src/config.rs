@@ -1,30 +1,760 @@
-#[derive(Deserialize, Debug)]
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+
+use once_cell::sync::OnceCell;
+use serde::de::{DeserializeOwned, IntoDeserializer};
+
+/// Settings, from the config file and environment variables
+///
+/// Every setting can be set in both, environment variables take precedence. The name of the
+/// environment variable is the setting's name in uppercase, with its section as prefix, so
+/// `ttl` in the `[cache]` section is `CACHE_TTL`.
+#[derive(Debug)]
 pub struct Config {
     bot_name: String,
     redis_url: Option<String>,
+    redis_sentinels: Option<Vec<String>>,
+    redis_sentinel_master: Option<String>,
+    redis_cluster_nodes: Option<Vec<String>>,
     opentelemetry_endpoint: Option<String>,
+    tracing_exporter: Option<TracingExporter>,
+    log_format: Option<LogFormat>,
+    tracing_sample_ratio: Option<f64>,
+    http_address: Option<String>,
+    update_mode: Option<UpdateMode>,
+    webhook_url: Option<String>,
+    webhook_address: Option<String>,
+    webhook_secret: Option<String>,
+    admin_users: Vec<i64>,
+    shutdown_timeout: Option<Duration>,
+    reminders_path: Option<String>,
+    commands_disabled: Vec<String>,
+    cache_backend: Option<CacheBackend>,
+    cache_memory_capacity: Option<usize>,
+    cache_file_path: Option<String>,
+    cache_ttls: CacheTtls,
+    cache_format: Option<CacheFormat>,
+    cache_compress_above: Option<usize>,
+    cache_distributed_lock: Option<bool>,
 }
 
-lazy_static! {
-    static ref CONFIG: Config = match envy::from_env::<Config>() {
-        Ok(config) => config,
-        Err(error) => panic!("Missing or incorrect environment variable: {}", error),
+/// Every problem found while loading the config
+#[derive(Debug, PartialEq)]
+pub struct ConfigErrors(pub Vec<String>);
+
+impl fmt::Display for ConfigErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Invalid configuration:")?;
+        for error in &self.0 {
+            writeln!(f, "  - {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConfigErrors {}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum CacheBackend {
+    Redis,
+    Memory,
+    File,
+    None,
+}
+
+/// Where spans are exported to
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TracingExporter {
+    /// a jaeger agent, over udp
+    Jaeger,
+    /// an OpenTelemetry collector, over grpc
+    Otlp,
+    None,
+}
+
+/// How log lines are written to stdout
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    Text,
+    /// a json object per line, with the fields of the spans the event happened in
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!("invalid log format `{}`", format)),
+        }
+    }
+}
+
+/// How updates are received from Telegram
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum UpdateMode {
+    Polling,
+    /// Telegram posts updates to `WEBHOOK_URL`, received on `WEBHOOK_ADDRESS`
+    Webhook,
+}
+
+/// How the redis cache is reached
+#[derive(Debug, Clone, PartialEq)]
+pub enum RedisTopology {
+    Standalone(&'static str),
+    /// ask the sentinels where the master is, `REDIS_URL` provides its credentials and database
+    Sentinel {
+        sentinels: Vec<&'static str>,
+        master: &'static str,
+    },
+    Cluster(Vec<&'static str>),
+}
+
+/// How values are serialized before being stored in the cache
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum CacheFormat {
+    Json,
+    Msgpack,
+}
+
+/// How long cached values are kept, per cached type
+#[derive(Debug, Clone)]
+pub struct CacheTtls {
+    pub default: Duration,
+    /// scoped entries, like the last images of a chat
+    pub scoped: Duration,
+    /// overrides for specific types, by `Cacheable::KIND`
+    pub kinds: HashMap<String, Duration>,
+}
+
+impl CacheTtls {
+    pub fn get(&self, kind: &str) -> Duration {
+        self.kinds.get(kind).copied().unwrap_or(self.default)
+    }
+
+    fn from_settings(settings: &mut Settings) -> Self {
+        let mut ttls = CacheTtls::default();
+
+        if let Some(ttl) = settings.with("cache_ttl", parse_duration) {
+            ttls.default = ttl;
+        }
+        if let Some(ttl) = settings.with("cache_scoped_ttl", parse_duration) {
+            ttls.scoped = ttl;
+        }
+
+        // formatted as `images=1h,wiki=7d`
+        if let Some(kinds) = settings.with("cache_ttls", |kinds| {
+            split_list(kinds)
+                .into_iter()
+                .map(|pair| {
+                    let (kind, ttl) = pair
+                        .split_once('=')
+                        .ok_or_else(|| format!("expected `type=duration`, got `{}`", pair))?;
+                    let kind = kind.trim();
+                    if !crate::ddg::CACHE_KINDS.contains(&kind) {
+                        return Err(format!(
+                            "unknown type `{}`, expected one of {}",
+                            kind,
+                            crate::ddg::CACHE_KINDS.join(", ")
+                        ));
+                    }
+                    Ok((kind.to_owned(), parse_duration(ttl)?))
+                })
+                .collect()
+        }) {
+            ttls.kinds = kinds;
+        }
+
+        ttls
+    }
+}
+
+impl Default for CacheTtls {
+    fn default() -> Self {
+        Self {
+            default: Duration::from_secs(3600 * 12),
+            scoped: Duration::from_secs(3600 * 24),
+            kinds: HashMap::new(),
+        }
+    }
+}
+
+/// The longest duration that can be configured, longer ttls and timeouts make no sense
+const MAX_DURATION: Duration = Duration::from_secs(3600 * 24 * 365);
+
+/// parse a duration like `90`, `90s`, `15m`, `12h` or `7d`, plain numbers are seconds
+/// it has to be longer than zero, and can be a year at most
+fn parse_duration(duration: &str) -> Result<Duration, String> {
+    let duration = duration.trim();
+    let (amount, unit) = match duration.find(|c: char| !c.is_ascii_digit()) {
+        Some(index) => duration.split_at(index),
+        None => (duration, "s"),
+    };
+
+    let amount: u64 = amount
+        .parse()
+        .map_err(|_| format!("invalid duration `{}`", duration))?;
+
+    let multiplier = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 3600 * 24,
+        _ => return Err(format!("invalid duration unit in `{}`", duration)),
     };
+
+    match amount.checked_mul(multiplier).map(Duration::from_secs) {
+        Some(parsed) if parsed.as_secs() == 0 => Err(format!(
+            "duration `{}` has to be longer than zero",
+            duration
+        )),
+        Some(parsed) if parsed <= MAX_DURATION => Ok(parsed),
+        _ => Err(format!(
+            "duration `{}` is longer than {} days",
+            duration,
+            MAX_DURATION.as_secs() / (3600 * 24)
+        )),
+    }
+}
+
+/// check an address like `127.0.0.1:8080` can be listened on
+fn parse_address(address: &str) -> Result<String, String> {
+    address
+        .parse::<SocketAddr>()
+        .map(|_| address.to_owned())
+        .map_err(|err| format!("{} in `{}`", err, address))
+}
+
+/// split a comma separated list, ignoring empty items
+fn split_list(list: &str) -> Vec<&str> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+/// The settings as they were written, before they're parsed
+struct Settings {
+    /// by lowercase name, environment variables replace the values from the config file
+    values: HashMap<String, String>,
+    /// settings from the config file, to report the ones that don't exist
+    file_settings: Vec<String>,
+    used: HashSet<String>,
+    errors: Vec<String>,
+}
+
+impl Settings {
+    fn new(path: Option<&Path>, env: impl IntoIterator<Item = (String, String)>) -> Self {
+        let mut settings = Settings {
+            values: HashMap::new(),
+            file_settings: Vec::new(),
+            used: HashSet::new(),
+            errors: Vec::new(),
+        };
+
+        if let Some(path) = path {
+            match std::fs::read_to_string(path)
+                .map_err(|err| err.to_string())
+                .and_then(|file| file.parse::<toml::Value>().map_err(|err| err.to_string()))
+            {
+                Ok(toml::Value::Table(table)) => {
+                    for (name, value) in flatten("", &table, &mut settings.errors) {
+                        settings.file_settings.push(name.clone());
+                        settings.values.insert(name, value);
+                    }
+                }
+                Ok(_) => settings
+                    .errors
+                    .push(format!("`{}` isn't a table", path.display())),
+                Err(err) => {
+                    settings
+                        .errors
+                        .push(format!("unable to read `{}`: {}", path.display(), err))
+                }
+            }
+        }
+
+        settings.values.extend(
+            env.into_iter()
+                .map(|(name, value)| (name.to_lowercase(), value)),
+        );
+        settings
+    }
+
+    fn get(&mut self, name: &str) -> Option<String> {
+        self.used.insert(name.to_owned());
+        self.values.get(name).cloned()
+    }
+
+    fn with<T>(&mut self, name: &str, parse: impl FnOnce(&str) -> Result<T, String>) -> Option<T> {
+        let value = self.get(name)?;
+        match parse(&value) {
+            Ok(value) => Some(value),
+            Err(err) => {
+                self.errors
+                    .push(format!("`{}`: {}", name.to_uppercase(), err));
+                None
+            }
+        }
+    }
+
+    fn parse<T>(&mut self, name: &str) -> Option<T>
+    where
+        T: FromStr,
+        T::Err: fmt::Display,
+    {
+        self.with(name, |value| {
+            value
+                .trim()
+                .parse()
+                .map_err(|err: T::Err| format!("{} in `{}`", err, value))
+        })
+    }
+
+    /// one of the variants of an enum, by its lowercase name
+    fn variant<T: DeserializeOwned>(&mut self, name: &str) -> Option<T> {
+        self.with(name, |value| {
+            T::deserialize(value.trim().into_deserializer())
+                .map_err(|err: serde::de::value::Error| err.to_string())
+        })
+    }
+
+    fn list(&mut self, name: &str) -> Option<Vec<String>> {
+        self.with(name, |list| {
+            Ok(split_list(list).into_iter().map(str::to_owned).collect())
+        })
+    }
+
+    fn require(&mut self, name: &str) -> String {
+        self.get(name).unwrap_or_else(|| {
+            self.errors
+                .push(format!("`{}` is required", name.to_uppercase()));
+            String::new()
+        })
+    }
+
+    fn finish(mut self) -> Result<(), ConfigErrors> {
+        for name in &self.file_settings {
+            if !self.used.contains(name) {
+                self.errors
+                    .push(format!("unknown setting `{}` in the config file", name));
+            }
+        }
+
+        match self.errors.is_empty() {
+            true => Ok(()),
+            false => Err(ConfigErrors(self.errors)),
+        }
+    }
+}
+
+/// flatten the config file to the names of the environment variables
+///
+/// Sections are used as prefix, lists are joined with commas, and tables within a section, like
+/// `[cache.ttls]`, become lists of `key=value` pairs.
+fn flatten(
+    prefix: &str,
+    table: &toml::value::Table,
+    errors: &mut Vec<String>,
+) -> Vec<(String, String)> {
+    let mut settings = Vec::new();
+
+    for (key, value) in table {
+        let name = match prefix {
+            "" => key.to_owned(),
+            prefix => format!("{}_{}", prefix, key),
+        };
+
+        let flattened = match value {
+            toml::Value::Table(table) if prefix.is_empty() => {
+                settings.append(&mut flatten(&name, table, errors));
+                continue;
+            }
+            toml::Value::Table(table) => table
+                .iter()
+                .map(|(key, value)| scalar(value).map(|value| format!("{}={}", key, value)))
+                .collect::<Option<Vec<_>>>()
+                .map(|pairs| pairs.join(",")),
+            toml::Value::Array(items) => items
+                .iter()
+                .map(scalar)
+                .collect::<Option<Vec<_>>>()
+                .map(|items| items.join(",")),
+            value => scalar(value),
+        };
+
+        match flattened {
+            Some(value) => settings.push((name, value)),
+            None => errors.push(format!(
+                "unsupported value for `{}` in the config file",
+                name
+            )),
+        }
+    }
+
+    settings
+}
+
+fn scalar(value: &toml::Value) -> Option<String> {
+    match value {
+        toml::Value::String(value) => Some(value.to_owned()),
+        toml::Value::Integer(value) => Some(value.to_string()),
+        toml::Value::Float(value) => Some(value.to_string()),
+        toml::Value::Boolean(value) => Some(value.to_string()),
+        _ => None,
+    }
+}
+
+static CONFIG: OnceCell<Config> = OnceCell::new();
+
+/// the loaded config, or the environment variables when it wasn't loaded
+fn config() -> &'static Config {
+    CONFIG.get_or_init(|| match Config::read(None, std::env::vars()) {
+        Ok(config) => config,
+        Err(errors) => panic!("{}", errors),
+    })
 }
 
 impl Config {
+    /// load the config file, if any, and the environment variables
+    ///
+    /// This has to happen before any setting is used, the config can't change afterwards.
+    pub fn load(path: Option<&Path>) -> Result<(), ConfigErrors> {
+        let config = Config::read(path, std::env::vars())?;
+        // it's only set already when a setting was used before loading
+        CONFIG.set(config).map_err(|_| {
+            ConfigErrors(vec![String::from(
+                "a setting was used before the config was loaded",
+            )])
+        })
+    }
+
+    fn read(
+        path: Option<&Path>,
+        env: impl IntoIterator<Item = (String, String)>,
+    ) -> Result<Self, ConfigErrors> {
+        let mut settings = Settings::new(path, env);
+
+        let config = Config {
+            bot_name: settings.require("bot_name"),
+            redis_url: settings.get("redis_url"),
+            redis_sentinels: settings.list("redis_sentinels"),
+            redis_sentinel_master: settings.get("redis_sentinel_master"),
+            redis_cluster_nodes: settings.list("redis_cluster_nodes"),
+            opentelemetry_endpoint: settings.get("opentelemetry_endpoint"),
+            tracing_exporter: settings.variant("tracing_exporter"),
+            log_format: settings.variant("log_format"),
+            tracing_sample_ratio: settings.with("tracing_sample_ratio", |ratio| {
+                match ratio.trim().parse::<f64>() {
+                    Ok(ratio) if (0.0..=1.0).contains(&ratio) => Ok(ratio),
+                    _ => Err(format!("expected a ratio between 0 and 1, got `{}`", ratio)),
+                }
+            }),
+            http_address: settings.with("http_address", parse_address),
+            update_mode: settings.variant("update_mode"),
+            webhook_url: settings.get("webhook_url"),
+            webhook_address: settings.with("webhook_address", parse_address),
+            webhook_secret: settings.get("webhook_secret"),
+            admin_users: settings
+                .with("admin_users", |users| {
+                    split_list(users)
+                        .into_iter()
+                        .map(|user| {
+                            user.parse()
+                                .map_err(|_| format!("invalid user id `{}`", user))
+                        })
+                        .collect()
+                })
+                .unwrap_or_default(),
+            shutdown_timeout: settings.with("shutdown_timeout", parse_duration),
+            reminders_path: settings.get("reminders_path"),
+            commands_disabled: settings
+                .with("commands_disabled", |commands| {
+                    let known = crate::commands::names();
+                    split_list(commands)
+                        .into_iter()
+                        .map(|command| match known.iter().any(|name| name == command) {
+                            true => Ok(command.to_owned()),
+                            false => Err(format!("unknown command `{}`", command)),
+                        })
+                        .collect()
+                })
+                .unwrap_or_default(),
+            cache_backend: settings.variant("cache_backend"),
+            cache_memory_capacity: settings.parse("cache_memory_capacity"),
+            cache_file_path: settings.get("cache_file_path"),
+            cache_ttls: CacheTtls::from_settings(&mut settings),
+            cache_format: settings.variant("cache_format"),
+            cache_compress_above: settings.parse("cache_compress_above"),
+            cache_distributed_lock: settings.parse("cache_distributed_lock"),
+        };
+
+        if config.update_mode == Some(UpdateMode::Webhook) && config.webhook_url.is_none() {
+            settings
+                .errors
+                .push(String::from("`WEBHOOK_URL` is required in webhook mode"));
+        }
+
+        settings.finish().map(|_| config)
+    }
+
     pub fn bot_name() -> &'static str {
-        &CONFIG.bot_name
+        &config().bot_name
     }
 
     pub fn redis_url() -> Option<&'static str> {
-        CONFIG.redis_url.as_ref().map(|url| url.as_ref())
+        config().redis_url.as_ref().map(|url| url.as_ref())
+    }
+
+    /// a cluster takes precedence over sentinels, which take precedence over a single `REDIS_URL`
+    pub fn redis_topology() -> Option<RedisTopology> {
+        if let Some(nodes) = &config().redis_cluster_nodes {
+            return Some(RedisTopology::Cluster(
+                nodes.iter().map(String::as_str).collect(),
+            ));
+        }
+        if let Some(sentinels) = &config().redis_sentinels {
+            return Some(RedisTopology::Sentinel {
+                sentinels: sentinels.iter().map(String::as_str).collect(),
+                master: config()
+                    .redis_sentinel_master
+                    .as_deref()
+                    .unwrap_or("mymaster"),
+            });
+        }
+
+        Config::redis_url().map(RedisTopology::Standalone)
+    }
+
+    /// defaults to jaeger when an endpoint is configured, spans aren't exported otherwise
+    pub fn tracing_exporter() -> TracingExporter {
+        match (config().tracing_exporter, &config().opentelemetry_endpoint) {
+            (Some(exporter), _) => exporter,
+            (None, Some(_)) => TracingExporter::Jaeger,
+            (None, None) => TracingExporter::None,
+        }
     }
 
     pub fn opentelemetry_endpoint() -> &'static str {
-        match &CONFIG.opentelemetry_endpoint {
-            Some(endpoint) => endpoint.as_ref(),
-            None => "127.0.0.1:6831",
+        match (&config().opentelemetry_endpoint, Config::tracing_exporter()) {
+            (Some(endpoint), _) => endpoint.as_ref(),
+            (None, TracingExporter::Otlp) => "http://127.0.0.1:4317",
+            (None, _) => "127.0.0.1:6831",
         }
     }
+
+    pub fn log_format() -> LogFormat {
+        config().log_format.unwrap_or(LogFormat::Text)
+    }
+
+    /// share of the traces started by the bot that are exported, between 0 and 1
+    pub fn tracing_sample_ratio() -> f64 {
+        config().tracing_sample_ratio.unwrap_or(1.0)
+    }
+
+    /// telegram ids of the users allowed to use admin commands
+    pub fn admin_users() -> &'static [i64] {
+        &config().admin_users
+    }
+
+    /// address the http server for status and metrics listens on, it doesn't run without one
+    pub fn http_address() -> Option<&'static str> {
+        config().http_address.as_deref()
+    }
+
+    pub fn update_mode() -> UpdateMode {
+        config().update_mode.unwrap_or(UpdateMode::Polling)
+    }
+
+    /// public url Telegram posts updates to in webhook mode
+    pub fn webhook_url() -> Option<&'static str> {
+        config().webhook_url.as_deref()
+    }
+
+    /// address Telegram's requests are received on in webhook mode, separate from `HTTP_ADDRESS`
+    pub fn webhook_address() -> &'static str {
+        config()
+            .webhook_address
+            .as_deref()
+            .unwrap_or("0.0.0.0:8080")
+    }
+
+    /// checked on every webhook request, a random one is used when it's not set
+    pub fn webhook_secret() -> Option<&'static str> {
+        config().webhook_secret.as_deref()
+    }
+
+    /// how long running commands get to finish when shutting down
+    pub fn shutdown_timeout() -> Duration {
+        config()
+            .shutdown_timeout
+            .unwrap_or_else(|| Duration::from_secs(30))
+    }
+
+    /// file pending reminders are kept in while the bot isn't running
+    pub fn reminders_path() -> &'static str {
+        config()
+            .reminders_path
+            .as_deref()
+            .unwrap_or("reminders.json")
+    }
+
+    /// commands that are ignored, by name
+    pub fn commands_disabled() -> &'static [String] {
+        &config().commands_disabled
+    }
+
+    /// defaults to redis when it's configured, and to an in-memory cache otherwise
+    pub fn cache_backend() -> CacheBackend {
+        match (config().cache_backend, Config::redis_topology()) {
+            (Some(backend), _) => backend,
+            (None, Some(_)) => CacheBackend::Redis,
+            (None, None) => CacheBackend::Memory,
+        }
+    }
+
+    /// maximum amount of entries in the in-memory cache
+    pub fn cache_memory_capacity() -> usize {
+        config().cache_memory_capacity.unwrap_or(1000)
+    }
+
+    /// directory the file cache stores its entries in
+    pub fn cache_file_path() -> &'static str {
+        config().cache_file_path.as_deref().unwrap_or("cache")
+    }
+
+    pub fn cache_ttls() -> &'static CacheTtls {
+        &config().cache_ttls
+    }
+
+    pub fn cache_format() -> CacheFormat {
+        config().cache_format.unwrap_or(CacheFormat::Msgpack)
+    }
+
+    /// cached values larger than this amount of bytes are compressed, 0 disables compression
+    pub fn cache_compress_above() -> Option<usize> {
+        match config().cache_compress_above {
+            Some(0) => None,
+            Some(threshold) => Some(threshold),
+            None => Some(1024),
+        }
+    }
+
+    /// coordinate fetching uncached values with other bot instances sharing the same cache
+    pub fn cache_distributed_lock() -> bool {
+        config().cache_distributed_lock.unwrap_or(false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("90"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("15m"), Ok(Duration::from_secs(900)));
+        assert_eq!(parse_duration(" 7d"), Ok(Duration::from_secs(604800)));
+        assert!(parse_duration("1w").is_err());
+        assert!(parse_duration("h").is_err());
+        assert!(parse_duration("0").is_err());
+        assert!(parse_duration("366d").is_err());
+        assert!(parse_duration("18446744073709551615d").is_err());
+    }
+
+    #[test]
+    fn flattens_config_files() {
+        let file: toml::Value = r#"
+            bot_name = "Dinkelberg"
+            admin_users = [1, 2]
+
+            [cache]
+            ttl = "1h"
+            ttls = { images = "1h", wiki = "7d" }
+        "#
+        .parse()
+        .unwrap();
+        let mut errors = Vec::new();
+
+        let mut settings = flatten("", file.as_table().unwrap(), &mut errors);
+        settings.sort();
+
+        assert_eq!(
+            settings,
+            vec![
+                (String::from("admin_users"), String::from("1,2")),
+                (String::from("bot_name"), String::from("Dinkelberg")),
+                (String::from("cache_ttl"), String::from("1h")),
+                (
+                    String::from("cache_ttls"),
+                    String::from("images=1h,wiki=7d")
+                ),
+            ]
+        );
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    fn environment_overrides_config_file() {
+        let path = std::env::temp_dir().join(format!("dinkelberg-{}.toml", rand::random::<u64>()));
+        std::fs::write(
+            &path,
+            "bot_name = \"file\"\n[cache]\nbackend = \"file\"\nmemory_capacity = 10\n",
+        )
+        .unwrap();
+
+        let config = Config::read(
+            Some(&path),
+            vec![(String::from("BOT_NAME"), String::from("env"))],
+        )
+        .unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(config.bot_name, "env");
+        assert_eq!(config.cache_backend, Some(CacheBackend::File));
+        assert_eq!(config.cache_memory_capacity, Some(10));
+    }
+
+    #[test]
+    fn lists_every_config_error() {
+        let env = vec![
+            (String::from("CACHE_BACKEND"), String::from("floppy")),
+            (String::from("CACHE_TTL"), String::from("1w")),
+            (String::from("ADMIN_USERS"), String::from("1,bob")),
+            (String::from("COMMANDS_DISABLED"), String::from("roll,rol")),
+            (String::from("CACHE_TTLS"), String::from("images=1h,wki=7d")),
+        ];
+
+        let errors = Config::read(None, env).unwrap_err();
+
+        assert_eq!(
+            errors.0,
+            vec![
+                "`BOT_NAME` is required",
+                "`ADMIN_USERS`: invalid user id `bob`",
+                "`COMMANDS_DISABLED`: unknown command `rol`",
+                "`CACHE_BACKEND`: unknown variant `floppy`, expected one of `redis`, `memory`, `file`, `none`",
+                "`CACHE_TTL`: invalid duration unit in `1w`",
+                "`CACHE_TTLS`: unknown type `wki`, expected one of images, wiki",
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_log_formats() {
+        assert_eq!("json".parse(), Ok(LogFormat::Json));
+        assert_eq!("text".parse(), Ok(LogFormat::Text));
+        assert!("xml".parse::<LogFormat>().is_err());
+    }
 }
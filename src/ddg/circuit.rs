@@ -0,0 +1,138 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::backoff::Backoff;
+
+/// How long requests are refused after consecutive rate limits
+const BACKOFF: Backoff = Backoff::new(Duration::from_secs(5), Duration::from_secs(60 * 10));
+
+lazy_static! {
+    static ref BREAKER: CircuitBreaker = CircuitBreaker::new(BACKOFF);
+}
+
+/// Shared by every DuckDuckGo request, so one rate limit makes all of them back off
+struct CircuitBreaker {
+    state: Mutex<State>,
+    backoff: Backoff,
+}
+
+#[derive(Default)]
+struct State {
+    /// consecutive rate limits or blocks since the last successful request
+    failures: u32,
+    open_until: Option<Instant>,
+}
+
+/// Why the circuit was opened
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(super) enum Trip {
+    /// DuckDuckGo answered with `429 Too Many Requests`
+    RateLimited,
+    /// DuckDuckGo refused the request or served a challenge page
+    Blocked,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case", tag = "state")]
+pub enum CircuitState {
+    /// requests are sent to DuckDuckGo
+    Closed,
+    /// requests are refused until the back-off period is over
+    Open { remaining_secs: u64, failures: u32 },
+}
+
+impl CircuitBreaker {
+    fn new(backoff: Backoff) -> Self {
+        Self {
+            state: Mutex::new(State::default()),
+            backoff,
+        }
+    }
+
+    /// returns the remaining back-off time if requests aren't allowed right now
+    fn check(&self) -> Result<(), Duration> {
+        let state = self.state.lock().expect("ddg circuit breaker poisoned");
+
+        match state.open_until {
+            Some(open_until) if open_until > Instant::now() => {
+                Err(open_until.saturating_duration_since(Instant::now()))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn trip(&self) -> Duration {
+        let mut state = self.state.lock().expect("ddg circuit breaker poisoned");
+
+        state.failures = state.failures.saturating_add(1);
+        let backoff = self.backoff.after(state.failures);
+        state.open_until = Some(Instant::now() + backoff);
+
+        backoff
+    }
+
+    fn reset(&self) {
+        let mut state = self.state.lock().expect("ddg circuit breaker poisoned");
+        *state = State::default();
+    }
+
+    fn state(&self) -> CircuitState {
+        let state = self.state.lock().expect("ddg circuit breaker poisoned");
+
+        match state.open_until {
+            Some(open_until) if open_until > Instant::now() => CircuitState::Open {
+                remaining_secs: open_until
+                    .saturating_duration_since(Instant::now())
+                    .as_secs(),
+                failures: state.failures,
+            },
+            _ => CircuitState::Closed,
+        }
+    }
+}
+
+pub(super) fn check() -> Result<(), Duration> {
+    BREAKER.check()
+}
+
+/// open the circuit after DuckDuckGo rate limited or blocked us
+pub(super) fn trip(reason: Trip) {
+    let backoff = BREAKER.trip();
+    match reason {
+        Trip::RateLimited => warn!("DDG is rate limiting us, backing off for {:?}", backoff),
+        Trip::Blocked => warn!("DDG is blocking us, backing off for {:?}", backoff),
+    }
+}
+
+/// close the circuit after a successful request
+pub(super) fn reset() {
+    BREAKER.reset()
+}
+
+pub fn state() -> CircuitState {
+    BREAKER.state()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_grows_exponentially() {
+        let breaker = CircuitBreaker::new(Backoff::new(
+            Duration::from_secs(5),
+            Duration::from_secs(60),
+        ));
+
+        assert_eq!(breaker.trip(), Duration::from_secs(5));
+        assert_eq!(breaker.trip(), Duration::from_secs(10));
+        assert_eq!(breaker.trip(), Duration::from_secs(20));
+        assert_eq!(breaker.trip(), Duration::from_secs(40));
+        assert_eq!(breaker.trip(), Duration::from_secs(60));
+        assert!(breaker.check().is_err());
+
+        breaker.reset();
+        assert!(breaker.check().is_ok());
+        assert_eq!(breaker.state(), CircuitState::Closed);
+    }
+}
@@ -0,0 +1,224 @@
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Utc};
+use reqwest::{StatusCode, Url};
+
+/// Response bodies are only kept for debugging purposes, there is no need to hold on to a whole HTML page
+const MAX_BODY_LEN: usize = 512;
+
+lazy_static! {
+    static ref LAST_ERROR: Mutex<Option<LastError>> = Mutex::new(None);
+}
+
+/// Cloned to share the outcome of one request with everyone waiting for it
+#[derive(Debug, Clone)]
+pub enum DuckDuckGoError {
+    /// the search page didn't contain a `vqd` token
+    TokenNotFound(ResponseDetails),
+    /// the image API refused our `vqd` token
+    TokenRejected(ResponseDetails),
+    EmptyResponse,
+    RateLimited(ResponseDetails),
+    Blocked(ResponseDetails),
+    /// requests are paused for this amount of seconds after being rate limited
+    CoolingDown(u64),
+    /// DuckDuckGo answered with a status code we don't know how to handle
+    UnexpectedStatus(ResponseDetails),
+    /// the request couldn't be sent or its response couldn't be decoded
+    Request {
+        url: Option<Url>,
+        status: Option<StatusCode>,
+        source: Arc<reqwest::Error>,
+    },
+}
+
+/// What DuckDuckGo answered, kept around to find out why a request failed
+#[derive(Debug, Clone)]
+pub struct ResponseDetails {
+    pub status: StatusCode,
+    pub url: Url,
+    /// the start of the response body
+    pub body: String,
+}
+
+/// The most recent DuckDuckGo failure, reported by the `/health` command
+///
+/// Only the kind of failure is kept, the response details are logged instead of shown in chats.
+#[derive(Debug, Clone)]
+pub struct LastError {
+    pub at: DateTime<Utc>,
+    pub outcome: &'static str,
+    pub status: Option<StatusCode>,
+}
+
+impl DuckDuckGoError {
+    /// true if DuckDuckGo doesn't want to hear from us for a while
+    pub fn is_throttled(&self) -> bool {
+        matches!(
+            self,
+            DuckDuckGoError::RateLimited(_)
+                | DuckDuckGoError::Blocked(_)
+                | DuckDuckGoError::CoolingDown(_)
+        )
+    }
+
+    /// a short name for the kind of failure, used as a metric label
+    pub fn outcome(&self) -> &'static str {
+        match self {
+            DuckDuckGoError::TokenNotFound(_) => "token_not_found",
+            DuckDuckGoError::TokenRejected(_) => "token_rejected",
+            DuckDuckGoError::EmptyResponse => "empty_response",
+            DuckDuckGoError::RateLimited(_) => "rate_limited",
+            DuckDuckGoError::Blocked(_) => "blocked",
+            DuckDuckGoError::CoolingDown(_) => "cooling_down",
+            DuckDuckGoError::UnexpectedStatus(_) => "unexpected_status",
+            DuckDuckGoError::Request { .. } => "request_failed",
+        }
+    }
+
+    /// the HTTP status DuckDuckGo answered with, if we got that far
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            DuckDuckGoError::TokenNotFound(details)
+            | DuckDuckGoError::TokenRejected(details)
+            | DuckDuckGoError::RateLimited(details)
+            | DuckDuckGoError::Blocked(details)
+            | DuckDuckGoError::UnexpectedStatus(details) => Some(details.status),
+            DuckDuckGoError::Request { status, .. } => *status,
+            DuckDuckGoError::EmptyResponse | DuckDuckGoError::CoolingDown(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for DuckDuckGoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DuckDuckGoError::TokenNotFound(details) => {
+                write!(f, "DDG API token not found in response ({})", details)
+            }
+            DuckDuckGoError::TokenRejected(details) => {
+                write!(f, "DDG rejected the API token ({})", details)
+            }
+            DuckDuckGoError::EmptyResponse => write!(f, "DDG responded with an empty answer"),
+            DuckDuckGoError::RateLimited(details) => {
+                write!(f, "DDG is rate limiting our requests ({})", details)
+            }
+            DuckDuckGoError::Blocked(details) => {
+                write!(f, "DDG is blocking our requests ({})", details)
+            }
+            DuckDuckGoError::CoolingDown(secs) => {
+                write!(f, "DDG requests are paused for another {} seconds", secs)
+            }
+            DuckDuckGoError::UnexpectedStatus(details) => {
+                write!(f, "Unexpected DDG server response ({})", details)
+            }
+            DuckDuckGoError::Request { url, source, .. } => match url {
+                Some(url) => write!(f, "DDG request to {} failed: {}", url, source),
+                None => write!(f, "DDG request failed: {}", source),
+            },
+        }
+    }
+}
+
+impl std::error::Error for DuckDuckGoError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DuckDuckGoError::Request { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for DuckDuckGoError {
+    fn from(error: reqwest::Error) -> DuckDuckGoError {
+        DuckDuckGoError::Request {
+            url: error.url().cloned(),
+            status: error.status(),
+            source: Arc::new(error),
+        }
+    }
+}
+
+impl ResponseDetails {
+    pub(super) fn new(status: StatusCode, url: Url, body: &str) -> Self {
+        Self {
+            status,
+            url,
+            body: truncate(body, MAX_BODY_LEN).to_owned(),
+        }
+    }
+
+    /// consume a response, keeping the details needed to report on it
+    pub(super) async fn read(resp: reqwest::Response) -> Self {
+        let status = resp.status();
+        let url = resp.url().clone();
+        let body = resp.text().await.unwrap_or_default();
+
+        Self::new(status, url, &body)
+    }
+}
+
+impl fmt::Display for ResponseDetails {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} from {}", self.status, self.url)?;
+        if !self.body.is_empty() {
+            write!(f, ": {}", self.body)?;
+        }
+        Ok(())
+    }
+}
+
+/// cut a string at `max` bytes without splitting a character in half
+fn truncate(body: &str, max: usize) -> &str {
+    if body.len() <= max {
+        return body;
+    }
+
+    let mut end = max;
+    while !body.is_char_boundary(end) {
+        end -= 1;
+    }
+    &body[..end]
+}
+
+/// remember a failure so it can be reported later on, expected answers like an empty lookup are ignored
+pub(super) fn record(error: DuckDuckGoError) -> DuckDuckGoError {
+    if matches!(
+        error,
+        DuckDuckGoError::EmptyResponse | DuckDuckGoError::CoolingDown(_)
+    ) {
+        return error;
+    }
+
+    tracing::error!(
+        status = ?error.status(),
+        source = ?std::error::Error::source(&error),
+        "{}",
+        error
+    );
+
+    let mut last_error = LAST_ERROR.lock().expect("ddg last error poisoned");
+    *last_error = Some(LastError {
+        at: Utc::now(),
+        outcome: error.outcome(),
+        status: error.status(),
+    });
+
+    error
+}
+
+pub fn last_error() -> Option<LastError> {
+    LAST_ERROR.lock().expect("ddg last error poisoned").clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn truncate_respects_char_boundaries() {
+        assert_eq!(truncate("short", 10), "short");
+        assert_eq!(truncate("🦆🦆", 5), "🦆");
+    }
+}
@@ -0,0 +1,402 @@
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+use crate::cache::{Cache, Cacheable};
+use crate::metrics::DDG_REQUESTS;
+
+use rand::seq::SliceRandom;
+use regex::Regex;
+use sha2::{Digest, Sha256};
+use unicode_normalization::UnicodeNormalization;
+
+mod circuit;
+mod error;
+mod token;
+
+use circuit::Trip;
+pub use circuit::{state as circuit_state, CircuitState};
+use error::ResponseDetails;
+pub use error::{last_error, DuckDuckGoError};
+pub use token::stats as token_stats;
+
+const BASE_URI: &str = "https://duckduckgo.com";
+const BASE_API_URI: &str = "https://api.duckduckgo.com";
+/// How long to wait for DuckDuckGo when checking if it's reachable
+const PING_TIMEOUT: Duration = Duration::from_secs(5);
+/// Normalized queries longer than this are hashed to keep cache keys bounded
+const MAX_KEY_LEN: usize = 128;
+/// The kinds of cached responses, their TTLs can be set with `CACHE_TTLS`
+pub const CACHE_KINDS: &[&str] = &[ImageResponse::KIND, WikiResponse::KIND];
+
+#[derive(Debug)]
+pub struct Client {
+    token: Option<String>,
+    reqwest: reqwest::Client,
+}
+
+impl Client {
+    fn new() -> Self {
+        Client {
+            token: None,
+            reqwest: crate::HTTP_CLIENT.clone(),
+        }
+    }
+
+    /// fetch and set the duckduckgo request token
+    /// This token is only valid for a specific request for a (currently unkown) amount of time
+    #[tracing::instrument(name = "ddg::acquire_token")]
+    async fn acquire_token(&mut self, query: &str) -> Result<&Client, DuckDuckGoError> {
+        let resp = Client::send(self.reqwest.get(BASE_URI).query(&[("q", query)])).await?;
+        let status = resp.status();
+        let url = resp.url().clone();
+        let details = |body: &str| ResponseDetails::new(status, url.clone(), body);
+        let body = resp.text().await?;
+
+        if Client::is_blocked(status, &body) {
+            circuit::trip(Trip::Blocked);
+            return Err(DuckDuckGoError::Blocked(details(&body)));
+        }
+
+        match Client::find_token(&body) {
+            Some(token) => {
+                token::insert(&cache_key(query), token.clone());
+                self.token = Some(token);
+            }
+            None => {
+                return Err(DuckDuckGoError::TokenNotFound(details(&body)));
+            }
+        }
+
+        Ok(&*self)
+    }
+
+    /// reuse a token acquired earlier for the same query, or fetch a new one
+    /// returns true if the token came from the token cache
+    async fn token(&mut self, query: &str) -> Result<bool, DuckDuckGoError> {
+        if let Some(token) = token::get(&cache_key(query)) {
+            self.token = Some(token);
+            return Ok(true);
+        }
+
+        self.acquire_token(query).await?;
+        Ok(false)
+    }
+
+    /// send a request to DuckDuckGo, unless we're still backing off from an earlier rate limit
+    async fn send(request: reqwest::RequestBuilder) -> Result<reqwest::Response, DuckDuckGoError> {
+        if let Err(remaining) = circuit::check() {
+            return Err(DuckDuckGoError::CoolingDown(remaining.as_secs()));
+        }
+
+        let resp = request.send().await?;
+
+        if resp.status() == reqwest::StatusCode::TOO_MANY_REQUESTS {
+            circuit::trip(Trip::RateLimited);
+            return Err(DuckDuckGoError::RateLimited(
+                ResponseDetails::read(resp).await,
+            ));
+        }
+
+        Ok(resp)
+    }
+
+    /// DuckDuckGo serves a challenge page instead of search results when it thinks we're a bot
+    fn is_blocked(status: reqwest::StatusCode, body: &str) -> bool {
+        status == reqwest::StatusCode::FORBIDDEN
+            || body.contains("anomaly-modal")
+            || body.contains("challenge-form")
+    }
+
+    /// look through a duckduckgo response and return the api token if it's present
+    fn find_token(haystack: &str) -> Option<String> {
+        lazy_static! {
+            static ref TOKEN_PATTERN: Regex =
+                Regex::new(r"vqd=([\d-]+)").expect("invalid ddg token regex");
+        }
+
+        TOKEN_PATTERN
+            .captures(haystack)
+            .and_then(|capture| capture.get(0))
+            .and_then(|token| token.as_str().split('=').next_back())
+            .map(|token| token.to_string())
+    }
+
+    /// the normalized query is sent, so the results and token match the key they're cached under
+    #[tracing::instrument(name = "ddg::search_images")]
+    pub async fn search_images(query: &str) -> Result<ImageResponse, DuckDuckGoError> {
+        let query = normalize_query(query);
+
+        Cache::get_or_fetch(cache_key(&query), || {
+            counted("images", Client::search(&query))
+        })
+        .await
+    }
+
+    async fn search(query: &str) -> Result<ImageResponse, DuckDuckGoError> {
+        let mut client = Client::new();
+        let cached_token = client.token(query).await?;
+
+        let res = match client.fetch_images(query).await {
+            Err(DuckDuckGoError::TokenRejected(_)) if cached_token => {
+                debug!("cached ddg token rejected, acquiring a new one");
+                token::invalidate(&cache_key(query));
+                client.acquire_token(query).await?;
+                client.fetch_images(query).await
+            }
+            res => res,
+        };
+
+        let res = match res {
+            // a freshly acquired token being refused means we're being blocked
+            Err(DuckDuckGoError::TokenRejected(details)) => {
+                circuit::trip(Trip::Blocked);
+                return Err(DuckDuckGoError::Blocked(details));
+            }
+            res => res?,
+        };
+        circuit::reset();
+
+        Ok(res)
+    }
+
+    #[tracing::instrument(name = "ddg::fetch_images")]
+    async fn fetch_images(&self, query: &str) -> Result<ImageResponse, DuckDuckGoError> {
+        let resp = Client::send(
+            self.reqwest
+                .get(format!("{}/i.js", BASE_URI).as_str())
+                .query(&[
+                    ("l", "us-en"),
+                    ("o", "json"),
+                    (
+                        "vqd",
+                        self.token
+                            .as_deref()
+                            .expect("By this point the DDG token should exist"),
+                    ),
+                    ("q", query),
+                ]),
+        )
+        .await?;
+
+        if resp.status() == reqwest::StatusCode::FORBIDDEN {
+            return Err(DuckDuckGoError::TokenRejected(
+                ResponseDetails::read(resp).await,
+            ));
+        }
+
+        if !resp.status().is_success() {
+            return Err(DuckDuckGoError::UnexpectedStatus(
+                ResponseDetails::read(resp).await,
+            ));
+        }
+
+        Ok(resp.json::<ImageResponse>().await?)
+    }
+
+    #[tracing::instrument(name = "ddg::wiki_lookup")]
+    pub async fn wiki_lookup(query: &str) -> Result<WikiResponse, DuckDuckGoError> {
+        let query = normalize_query(query);
+
+        Cache::get_or_fetch(cache_key(&query), || {
+            counted("wiki", Client::lookup(&query))
+        })
+        .await
+    }
+
+    async fn lookup(query: &str) -> Result<WikiResponse, DuckDuckGoError> {
+        let resp = Client::send(crate::HTTP_CLIENT.get(BASE_API_URI).query(&[
+            ("q", query),
+            ("format", "json"),
+            ("no_html", "1"),
+            ("skip_disambig", "1"),
+        ]))
+        .await?;
+
+        if resp.status() == reqwest::StatusCode::FORBIDDEN {
+            circuit::trip(Trip::Blocked);
+            return Err(DuckDuckGoError::Blocked(ResponseDetails::read(resp).await));
+        }
+
+        if !resp.status().is_success() {
+            return Err(DuckDuckGoError::UnexpectedStatus(
+                ResponseDetails::read(resp).await,
+            ));
+        }
+
+        let res = resp.json::<WikiResponse>().await?;
+        circuit::reset();
+
+        if res.abstract_text.is_empty() {
+            return Err(DuckDuckGoError::EmptyResponse);
+        }
+
+        Ok(res)
+    }
+}
+
+/// count and log the outcome of a request that went out to DuckDuckGo, instead of being answered
+/// from cache, so one shared by concurrent commands is only reported once
+///
+/// Requests refused while the circuit is open never reach DuckDuckGo, so they aren't counted.
+async fn counted<T>(
+    endpoint: &'static str,
+    request: impl Future<Output = Result<T, DuckDuckGoError>>,
+) -> Result<T, DuckDuckGoError> {
+    if let Err(remaining) = circuit::check() {
+        return Err(DuckDuckGoError::CoolingDown(remaining.as_secs()));
+    }
+
+    let res = request.await;
+    let outcome = match &res {
+        Ok(_) => "ok",
+        Err(err) => err.outcome(),
+    };
+    DDG_REQUESTS.with_label_values(&[endpoint, outcome]).inc();
+
+    res.map_err(error::record)
+}
+
+/// check if DuckDuckGo can be reached, returns how long it took to respond
+/// any response counts, this doesn't go through or affect the circuit breaker
+pub async fn ping() -> Result<Duration, DuckDuckGoError> {
+    let started = Instant::now();
+    crate::HTTP_CLIENT
+        .head(BASE_URI)
+        .timeout(PING_TIMEOUT)
+        .send()
+        .await?;
+
+    Ok(started.elapsed())
+}
+
+/// normalize unicode, lowercase the query and collapse its whitespace,
+/// so trivially different queries share cached data
+fn normalize_query(query: &str) -> String {
+    query
+        .nfkc()
+        .collect::<String>()
+        .split_whitespace()
+        .map(|word| word.to_lowercase())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// the key under which results for this query are cached, long queries are replaced by their hash
+fn cache_key(query: &str) -> String {
+    let query = normalize_query(query);
+
+    if query.len() <= MAX_KEY_LEN {
+        return query;
+    }
+
+    format!("sha256:{:x}", Sha256::digest(query.as_bytes()))
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ImageResponse {
+    query: String,
+    results: Vec<Image>,
+}
+
+impl Cacheable for ImageResponse {
+    const KIND: &'static str = "images";
+    const VERSION: u32 = 1;
+}
+
+impl ImageResponse {
+    pub fn random(&self) -> Option<&Image> {
+        self.results.choose(&mut rand::thread_rng())
+    }
+
+    pub fn first(&self) -> Option<&Image> {
+        self.results.first()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Image {
+    width: i32,
+    height: i32,
+    /// URL to the page
+    url: String,
+    source: String,
+    title: String,
+    /// URL to the actual image
+    image: String,
+}
+
+impl Image {
+    pub fn image_url(&self) -> &str {
+        &self.image
+    }
+
+    #[tracing::instrument]
+    pub async fn download(&self) -> Result<bytes::Bytes, reqwest::Error> {
+        reqwest::get(&self.image).await?.bytes().await
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct WikiResponse {
+    abstract_text: String,
+}
+
+impl Cacheable for WikiResponse {
+    const KIND: &'static str = "wiki";
+    const VERSION: u32 = 1;
+}
+
+impl From<WikiResponse> for String {
+    fn from(res: WikiResponse) -> String {
+        res.abstract_text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_token() {
+        let token = Client::find_token("nrj('/d.js?q=test&t=D&l=us-en&s=0&dl=en&ct=BE&ss_mkt=us&vqd=3-322225378556065850860803507288131703155-133178935652763664263271092398831973244&p_ent=&ex=-1&sp=0');");
+        assert!(token.is_some());
+        assert_eq!(
+            token.unwrap(),
+            String::from(
+                "3-322225378556065850860803507288131703155-133178935652763664263271092398831973244"
+            )
+        );
+    }
+
+    #[test]
+    fn detects_challenge_page() {
+        assert!(Client::is_blocked(
+            reqwest::StatusCode::OK,
+            r#"<div class="anomaly-modal__title">Unfortunately, bots use DuckDuckGo too.</div>"#
+        ));
+        assert!(Client::is_blocked(reqwest::StatusCode::FORBIDDEN, ""));
+        assert!(!Client::is_blocked(
+            reqwest::StatusCode::OK,
+            "nrj('/d.js?q=test&vqd=3-1234');"
+        ));
+    }
+
+    #[test]
+    fn normalize_query() {
+        assert_eq!(
+            super::normalize_query("  Cats   and\tDogs "),
+            "cats and dogs"
+        );
+        assert_eq!(super::normalize_query("ＣＡＴＳ"), "cats");
+    }
+
+    #[test]
+    fn cache_key_is_bounded() {
+        assert_eq!(cache_key("Cats "), "cats");
+
+        let key = cache_key(&"cats ".repeat(100));
+        assert!(key.starts_with("sha256:"));
+        assert_eq!(key.len(), "sha256:".len() + 64);
+    }
+}
@@ -0,0 +1,152 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// DuckDuckGo doesn't document how long a `vqd` token stays valid, ten minutes is well within what we've observed
+const TOKEN_TTL: Duration = Duration::from_secs(60 * 10);
+/// Upper bound on the amount of tokens kept around, the oldest one is evicted once this is reached
+const MAX_TOKENS: usize = 1024;
+
+lazy_static! {
+    static ref TOKENS: TokenCache = TokenCache::new(TOKEN_TTL, MAX_TOKENS);
+}
+
+struct Token {
+    value: String,
+    acquired: Instant,
+}
+
+/// In-process store of `vqd` tokens, keyed by normalized query
+struct TokenCache {
+    tokens: Mutex<HashMap<String, Token>>,
+    ttl: Duration,
+    capacity: usize,
+    hits: AtomicUsize,
+    misses: AtomicUsize,
+}
+
+#[derive(Serialize, Debug, Clone, Copy)]
+pub struct TokenStats {
+    pub hits: usize,
+    pub misses: usize,
+}
+
+impl TokenCache {
+    fn new(ttl: Duration, capacity: usize) -> Self {
+        Self {
+            tokens: Mutex::new(HashMap::new()),
+            ttl,
+            capacity,
+            hits: AtomicUsize::default(),
+            misses: AtomicUsize::default(),
+        }
+    }
+
+    fn get(&self, query: &str) -> Option<String> {
+        let mut tokens = self.tokens.lock().expect("ddg token cache poisoned");
+
+        let token = match tokens.get(query) {
+            Some(token) if token.acquired.elapsed() < self.ttl => Some(token.value.clone()),
+            Some(_) => {
+                tokens.remove(query);
+                None
+            }
+            None => None,
+        };
+
+        match token {
+            Some(_) => self.hits.fetch_add(1, Ordering::Relaxed),
+            None => self.misses.fetch_add(1, Ordering::Relaxed),
+        };
+
+        token
+    }
+
+    fn insert(&self, query: &str, value: String) {
+        let mut tokens = self.tokens.lock().expect("ddg token cache poisoned");
+
+        if tokens.len() >= self.capacity && !tokens.contains_key(query) {
+            let ttl = self.ttl;
+            tokens.retain(|_, token| token.acquired.elapsed() < ttl);
+        }
+
+        if tokens.len() >= self.capacity && !tokens.contains_key(query) {
+            let oldest = tokens
+                .iter()
+                .min_by_key(|(_, token)| token.acquired)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                tokens.remove(&oldest);
+            }
+        }
+
+        tokens.insert(
+            query.to_owned(),
+            Token {
+                value,
+                acquired: Instant::now(),
+            },
+        );
+    }
+
+    fn invalidate(&self, query: &str) {
+        let mut tokens = self.tokens.lock().expect("ddg token cache poisoned");
+        tokens.remove(query);
+    }
+
+    fn stats(&self) -> TokenStats {
+        TokenStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// return a previously acquired token for this query if it hasn't expired yet
+pub(super) fn get(query: &str) -> Option<String> {
+    TOKENS.get(query)
+}
+
+pub(super) fn insert(query: &str, token: String) {
+    TOKENS.insert(query, token)
+}
+
+/// forget the token for this query, used when DuckDuckGo rejects it
+pub(super) fn invalidate(query: &str) {
+    TOKENS.invalidate(query)
+}
+
+pub fn stats() -> TokenStats {
+    TOKENS.stats()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expired_tokens_are_misses() {
+        let cache = TokenCache::new(Duration::from_secs(0), 10);
+        cache.insert("cats", String::from("3-123"));
+
+        assert_eq!(cache.get("cats"), None);
+        assert_eq!(cache.stats().misses, 1);
+        assert_eq!(cache.stats().hits, 0);
+    }
+
+    #[test]
+    fn evicts_oldest_token_when_full() {
+        let cache = TokenCache::new(Duration::from_secs(60), 2);
+        cache.insert("cats", String::from("1"));
+        std::thread::sleep(Duration::from_millis(1));
+        cache.insert("dogs", String::from("2"));
+        std::thread::sleep(Duration::from_millis(1));
+        cache.insert("birds", String::from("3"));
+
+        assert_eq!(cache.get("cats"), None);
+        assert_eq!(cache.get("dogs"), Some(String::from("2")));
+        assert_eq!(cache.get("birds"), Some(String::from("3")));
+        assert_eq!(cache.stats().hits, 2);
+    }
+}
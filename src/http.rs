@@ -0,0 +1,175 @@
+use std::convert::Infallible;
+use std::error::Error;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use anyhow::Context;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{header, Body, Method, Request, Response, Server, StatusCode};
+use serde::Serialize;
+use teloxide::prelude::*;
+use teloxide::RequestError;
+
+use crate::cache::{Cache, CacheStatus};
+use crate::ddg::{self, CircuitState};
+use crate::metrics;
+use crate::BOT;
+
+/// How long to wait for Telegram when checking readiness
+const TELEGRAM_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Reported by the liveness and readiness endpoints
+#[derive(Serialize, Debug)]
+struct Probe {
+    /// liveness is always ok, readiness requires a reachable Telegram
+    ok: bool,
+    /// only checked for readiness, and only reported since the bot works without a cache
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cache: Option<CacheStatus>,
+    /// only checked for readiness
+    #[serde(skip_serializing_if = "Option::is_none")]
+    telegram: Option<TelegramStatus>,
+    ddg: CircuitState,
+}
+
+#[derive(Serialize, Debug)]
+struct TelegramStatus {
+    reachable: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Bind an http server to an address, the returned future serves the requests
+///
+/// The address is bound right away, so a bad or used address is reported before it's needed.
+pub(crate) fn bind<H, F>(address: &str, handler: H) -> anyhow::Result<impl Future<Output = ()>>
+where
+    H: Fn(Request<Body>) -> F + Clone + Send + Sync + 'static,
+    F: Future<Output = Response<Body>> + Send + 'static,
+{
+    let address: SocketAddr = address
+        .parse()
+        .with_context(|| format!("invalid http address `{}`", address))?;
+    // hyper's error already describes its cause
+    let server = Server::try_bind(&address)
+        .map_err(|err| anyhow::anyhow!("unable to serve http on {}: {}", address, err))?;
+
+    let make_service = make_service_fn(move |_| {
+        let handler = handler.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req| {
+                let res = handler(req);
+                async move { Ok::<_, Infallible>(res.await) }
+            }))
+        }
+    });
+
+    info!("serving http on {}", address);
+    Ok(async move {
+        if let Err(err) = server.serve(make_service).await {
+            error!("http server stopped: {}", err);
+        }
+    })
+}
+
+/// Serve the bot's status, for monitoring and the liveness and readiness probes
+#[tracing::instrument(name = "http::route", skip(req), fields(path = %req.uri().path()))]
+pub(crate) async fn route(req: Request<Body>) -> Response<Body> {
+    match (req.method(), req.uri().path()) {
+        (&Method::GET, "/stats") => json(StatusCode::OK, &Cache::status().await),
+        (&Method::GET, "/metrics") => match metrics::render() {
+            Ok(body) => Response::builder()
+                .header(header::CONTENT_TYPE, prometheus::TEXT_FORMAT)
+                .body(Body::from(body))
+                .unwrap_or_else(|_| empty(StatusCode::INTERNAL_SERVER_ERROR)),
+            Err(err) => {
+                error!("unable to render metrics: {}", err);
+                empty(StatusCode::INTERNAL_SERVER_ERROR)
+            }
+        },
+        (&Method::GET, "/livez") => json(StatusCode::OK, &live()),
+        (&Method::GET, "/readyz") => {
+            let probe = ready().await;
+            let status = if probe.ok {
+                StatusCode::OK
+            } else {
+                StatusCode::SERVICE_UNAVAILABLE
+            };
+            json(status, &probe)
+        }
+        _ => empty(StatusCode::NOT_FOUND),
+    }
+}
+
+/// the bot is running, regardless of the services it depends on
+fn live() -> Probe {
+    Probe {
+        ok: true,
+        cache: None,
+        telegram: None,
+        ddg: ddg::circuit_state(),
+    }
+}
+
+/// the bot can handle commands, an unhealthy cache or DuckDuckGo cooling down doesn't stop it
+///
+/// Both checks are bounded by a timeout, so a slow service fails the probe instead of hanging it.
+async fn ready() -> Probe {
+    let (cache, telegram) = tokio::join!(Cache::status(), telegram());
+
+    Probe {
+        ok: telegram.reachable,
+        cache: Some(cache),
+        telegram: Some(telegram),
+        ddg: ddg::circuit_state(),
+    }
+}
+
+async fn telegram() -> TelegramStatus {
+    let error = match tokio::time::timeout(TELEGRAM_TIMEOUT, BOT.get_me()).await {
+        Ok(Ok(_)) => None,
+        Ok(Err(err)) => Some(telegram_error(&err)),
+        Err(_) => Some(String::from("timed out")),
+    };
+
+    if let Some(error) = &error {
+        warn!("telegram is unreachable: {}", error);
+    }
+
+    TelegramStatus {
+        reachable: error.is_none(),
+        error,
+    }
+}
+
+/// describe a failed Telegram request, leaving out the url since it contains the bot token
+pub(crate) fn telegram_error(err: &RequestError) -> String {
+    match err {
+        RequestError::NetworkError(err) => match err.source() {
+            Some(source) => format!("network error: {}", source),
+            None => String::from("network error"),
+        },
+        err => err.to_string(),
+    }
+}
+
+fn json<T: Serialize>(status: StatusCode, body: &T) -> Response<Body> {
+    match serde_json::to_vec(body) {
+        Ok(body) => Response::builder()
+            .status(status)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body))
+            .unwrap_or_else(|_| empty(StatusCode::INTERNAL_SERVER_ERROR)),
+        Err(err) => {
+            error!("unable to serialize http response: {}", err);
+            empty(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+pub(crate) fn empty(status: StatusCode) -> Response<Body> {
+    let mut res = Response::new(Body::empty());
+    *res.status_mut() = status;
+    res
+}
@@ -7,58 +7,210 @@ extern crate log;
 #[macro_use]
 extern crate serde_derive;
 
+use std::fmt::Debug;
+use std::time::Instant;
+
 use structopt::StructOpt;
+use teloxide::dispatching::update_listeners::{self, UpdateListener};
 use teloxide::prelude::*;
 use teloxide::utils::command::BotCommand;
-use tracing_subscriber::layer::SubscriberExt;
-use tracing_subscriber::prelude::*;
+use tokio_stream::wrappers::UnboundedReceiverStream;
 
+mod backoff;
 mod cache;
 mod cli;
 mod commands;
 mod config;
 mod ddg;
+mod http;
+mod metrics;
+mod telemetry;
+mod webhook;
 
+use cli::{RunOpt, Subcommand};
 use commands::{responder, Command};
-use config::Config;
+use config::{Config, ConfigErrors, UpdateMode};
+use webhook::Webhook;
 
 lazy_static! {
+    /// when the bot started, to report its uptime
+    static ref STARTED: Instant = Instant::now();
     pub static ref BOT: AutoSend<teloxide::Bot> = Bot::from_env().auto_send();
     static ref HTTP_CLIENT: reqwest::Client = reqwest::Client::new();
 }
 
 #[tokio::main]
 async fn main() {
+    lazy_static::initialize(&STARTED);
     dotenv::dotenv().ok();
 
     let opt = cli::Opt::from_args();
+    let subcommand = opt.subcommand.unwrap_or(Subcommand::Run(opt.run));
 
-    if opt.commands {
+    if let Subcommand::Commands = subcommand {
         println!("{}", Command::descriptions());
-        std::process::exit(0);
+        return;
+    }
+
+    if let Err(errors) = Config::load(opt.config.as_deref()) {
+        eprint!("{}", errors);
+        std::process::exit(1);
     }
 
-    let tracer = opentelemetry_jaeger::new_pipeline()
-        .with_service_name(Config::bot_name())
-        .with_agent_endpoint(Config::opentelemetry_endpoint())
-        .install_simple()
-        .expect("unable to connect to opentelemetry agent");
+    let ok = match subcommand {
+        Subcommand::Run(run_opt) => run(run_opt).await,
+        Subcommand::CheckConfig => cli::check_config().await,
+        Subcommand::Send { chat_id, text } => cli::send(chat_id, text).await,
+        Subcommand::Reminders(command) => cli::reminders(command).await,
+        Subcommand::Commands => unreachable!("the commands are printed before loading the config"),
+    };
+
+    if !ok {
+        std::process::exit(1);
+    }
+}
 
-    // Create a tracing layer with the configured tracer
-    let opentelemetry = tracing_opentelemetry::layer().with_tracer(tracer);
+/// run the bot until it's shut down, false when it couldn't start
+async fn run(opt: RunOpt) -> bool {
+    let mode = if opt.webhook {
+        UpdateMode::Webhook
+    } else {
+        Config::update_mode()
+    };
+    // `--webhook` isn't part of the config, so it's checked here
+    let webhook_url = match (mode, Config::webhook_url()) {
+        (UpdateMode::Webhook, None) => {
+            eprint!(
+                "{}",
+                ConfigErrors(vec![String::from(
+                    "`WEBHOOK_URL` is required in webhook mode"
+                )])
+            );
+            return false;
+        }
+        (UpdateMode::Webhook, url) => url,
+        (UpdateMode::Polling, _) => None,
+    };
 
-    tracing_subscriber::registry()
-        .with(tracing_subscriber::fmt::layer().with_writer(std::io::stdout))
-        .with(tracing_subscriber::EnvFilter::from_default_env())
-        .with(opentelemetry)
-        .try_init()
-        .expect("unable to initialize the tokio tracer");
+    telemetry::init(opt.log_format.unwrap_or_else(Config::log_format));
 
+    metrics::init();
     cache::Cache::init();
 
     info!("Starting bot...");
     lazy_static::initialize(&BOT);
 
-    info!("Ready to start listening for messages");
-    teloxide::commands_repl(BOT.clone(), Config::bot_name(), responder).await;
+    tokio::spawn(commands::menu::register());
+
+    match commands::reminders::restore().await {
+        Ok(0) => {}
+        Ok(count) => info!("scheduled {} saved reminders", count),
+        Err(err) => error!("unable to restore reminders: {}", err),
+    }
+
+    // the bot runs without its status, so it's not a reason to stop
+    if let Some(address) = Config::http_address() {
+        match http::bind(address, http::route) {
+            Ok(server) => {
+                tokio::spawn(server);
+            }
+            Err(err) => error!("{:#}", err),
+        }
+    }
+
+    match webhook_url {
+        None => {
+            info!("Ready to start listening for messages");
+            dispatch(update_listeners::polling_default(BOT.clone()).await).await;
+        }
+        Some(url) => {
+            let listener = match Webhook::start(url, Config::webhook_address()).await {
+                Ok(listener) => listener,
+                Err(err) => {
+                    error!("{:#}", err);
+                    telemetry::shutdown();
+                    return false;
+                }
+            };
+
+            info!("Ready to receive messages on {}", url);
+            dispatch(listener).await;
+
+            Webhook::unregister().await;
+        }
+    }
+
+    match commands::reminders::persist().await {
+        Ok(0) => {}
+        Ok(count) => info!("saved {} pending reminders", count),
+        Err(err) => error!("unable to save reminders: {}", err),
+    }
+
+    telemetry::shutdown();
+    true
+}
+
+/// Handle commands until the listener stops, or until the bot is asked to shut down
+///
+/// Once a shutdown signal is received no new updates are accepted, and running commands get
+/// `SHUTDOWN_TIMEOUT` to finish.
+async fn dispatch<L, E>(listener: L)
+where
+    L: UpdateListener<E> + Send,
+    E: Debug + Send,
+{
+    let mut dispatcher = Dispatcher::new(BOT.clone()).messages_handler(
+        |rx: DispatcherHandlerRx<AutoSend<Bot>, Message>| {
+            UnboundedReceiverStream::new(rx)
+                .commands::<Command, _>(Config::bot_name())
+                .for_each_concurrent(None, |(cx, command)| async move {
+                    responder(cx, command).await.log_on_error().await;
+                })
+        },
+    );
+    let token = dispatcher.shutdown_token();
+
+    let dispatching = dispatcher.dispatch_with_listener(
+        listener,
+        LoggingErrorHandler::with_custom_text("An error from the update listener"),
+    );
+    tokio::pin!(dispatching);
+
+    tokio::select! {
+        _ = &mut dispatching => return,
+        _ = shutdown_signal() => {}
+    }
+
+    info!("Shutting down, waiting for running commands to finish");
+    if token.shutdown().is_err() {
+        return;
+    }
+    if tokio::time::timeout(Config::shutdown_timeout(), dispatching)
+        .await
+        .is_err()
+    {
+        warn!(
+            "Dispatching didn't stop within {:?}, shutting down anyway",
+            Config::shutdown_timeout()
+        );
+    }
+}
+
+/// resolves on ctrl-c, or when the container is stopped
+#[cfg(unix)]
+async fn shutdown_signal() {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut terminate = signal(SignalKind::terminate()).expect("unable to listen for SIGTERM");
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = terminate.recv() => {}
+    }
+}
+
+#[cfg(not(unix))]
+async fn shutdown_signal() {
+    tokio::signal::ctrl_c()
+        .await
+        .expect("unable to listen for ctrl-c");
 }
@@ -0,0 +1,75 @@
+use prometheus::{
+    register_histogram_vec, register_int_counter_vec, register_int_gauge, Encoder, HistogramVec,
+    IntCounterVec, IntGauge, TextEncoder,
+};
+use teloxide::RequestError;
+
+lazy_static! {
+    pub static ref COMMANDS: IntCounterVec = register_int_counter_vec!(
+        "dinkelberg_commands_total",
+        "Commands handled, by command",
+        &["command"]
+    )
+    .expect("unable to register the command counter");
+    pub static ref COMMAND_DURATION: HistogramVec = register_histogram_vec!(
+        "dinkelberg_command_duration_seconds",
+        "Time spent handling a command, by command",
+        &["command"]
+    )
+    .expect("unable to register the command histogram");
+    pub static ref DDG_REQUESTS: IntCounterVec = register_int_counter_vec!(
+        "dinkelberg_ddg_requests_total",
+        "Requests sent to DuckDuckGo, by endpoint and outcome",
+        &["endpoint", "outcome"]
+    )
+    .expect("unable to register the ddg request counter");
+    pub static ref CACHE_LOOKUPS: IntCounterVec = register_int_counter_vec!(
+        "dinkelberg_cache_lookups_total",
+        "Cache lookups, by cached type and result",
+        &["kind", "result"]
+    )
+    .expect("unable to register the cache lookup counter");
+    pub static ref PENDING_REMINDERS: IntGauge = register_int_gauge!(
+        "dinkelberg_pending_reminders",
+        "Reminders waiting for their deadline"
+    )
+    .expect("unable to register the pending reminders gauge");
+    pub static ref TELEGRAM_ERRORS: IntCounterVec = register_int_counter_vec!(
+        "dinkelberg_telegram_errors_total",
+        "Failed Telegram API requests, by command and kind of error",
+        &["command", "kind"]
+    )
+    .expect("unable to register the telegram error counter");
+}
+
+/// register every metric, so they're reported before they're first used
+pub(crate) fn init() {
+    lazy_static::initialize(&COMMANDS);
+    lazy_static::initialize(&COMMAND_DURATION);
+    lazy_static::initialize(&DDG_REQUESTS);
+    lazy_static::initialize(&CACHE_LOOKUPS);
+    lazy_static::initialize(&PENDING_REMINDERS);
+    lazy_static::initialize(&TELEGRAM_ERRORS);
+}
+
+/// count a failed Telegram API request made while handling a command
+pub(crate) fn telegram_error(command: &str, error: &RequestError) {
+    let kind = match error {
+        RequestError::ApiError { .. } => "api",
+        RequestError::MigrateToChatId(_) => "migrated",
+        RequestError::RetryAfter(_) => "retry_after",
+        RequestError::NetworkError(_) => "network",
+        RequestError::InvalidJson(_) => "invalid_json",
+        RequestError::Io(_) => "io",
+    };
+
+    TELEGRAM_ERRORS.with_label_values(&[command, kind]).inc();
+}
+
+/// every metric in the Prometheus text format
+pub(crate) fn render() -> Result<Vec<u8>, prometheus::Error> {
+    let mut buffer = Vec::new();
+    TextEncoder::new().encode(&prometheus::gather(), &mut buffer)?;
+
+    Ok(buffer)
+}
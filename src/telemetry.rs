@@ -0,0 +1,111 @@
+use opentelemetry::sdk::trace::{self, Sampler, Tracer, TracerProvider};
+use opentelemetry::sdk::Resource;
+use opentelemetry::trace::{TraceContextExt, TraceError, TracerProvider as _};
+use opentelemetry::KeyValue;
+use tracing::Span;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::fmt::time::ChronoUtc;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+
+use crate::config::{Config, LogFormat, TracingExporter};
+
+/// Log to stdout, and export spans when an exporter is configured
+///
+/// When the exporter can't be set up the bot keeps running, with just the logs.
+/// Spans get a trace id either way, so log lines can be correlated without an exporter.
+pub(crate) fn init(format: LogFormat) {
+    let (tracer, error) = match tracer() {
+        Ok(tracer) => (tracer, None),
+        Err(err) => (local_tracer(), Some(err)),
+    };
+
+    tracing_subscriber::registry()
+        .with(match format {
+            LogFormat::Text => Some(tracing_subscriber::fmt::layer().with_writer(std::io::stdout)),
+            LogFormat::Json => None,
+        })
+        .with(match format {
+            LogFormat::Json => Some(
+                tracing_subscriber::fmt::layer()
+                    .json()
+                    .with_timer(ChronoUtc::rfc3339())
+                    .with_current_span(true)
+                    .with_span_list(true)
+                    .with_writer(std::io::stdout),
+            ),
+            LogFormat::Text => None,
+        })
+        .with(tracing_subscriber::EnvFilter::from_default_env())
+        .with(tracing_opentelemetry::layer().with_tracer(tracer))
+        .try_init()
+        .expect("unable to initialize the tokio tracer");
+
+    if let Some(err) = error {
+        error!(
+            "unable to set up the {:?} exporter, spans won't be exported: {}",
+            Config::tracing_exporter(),
+            err
+        );
+    }
+}
+
+/// add the OpenTelemetry trace id to a span's `trace_id` field, so it ends up in its log lines
+pub(crate) fn record_trace_id(span: &Span) {
+    let context = span.context();
+    let span_context = context.span().span_context();
+
+    if span_context.is_valid() {
+        span.record("trace_id", &span_context.trace_id().to_hex().as_str());
+    }
+}
+
+/// export the spans that are still buffered
+pub(crate) fn shutdown() {
+    opentelemetry::global::shutdown_tracer_provider();
+}
+
+/// a tracer that doesn't export anything, it only gives spans their ids
+fn local_tracer() -> Tracer {
+    let provider = TracerProvider::builder()
+        .with_config(trace_config())
+        .build();
+    let tracer = provider.get_tracer("dinkelberg", None);
+    // the tracer only holds on to its provider weakly, as the global one it stays alive
+    let _ = opentelemetry::global::set_tracer_provider(provider);
+    tracer
+}
+
+fn trace_config() -> trace::Config {
+    // follow the caller's decision when a trace started elsewhere
+    trace::config()
+        .with_sampler(Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(
+            Config::tracing_sample_ratio(),
+        ))))
+        .with_resource(Resource::new(vec![KeyValue::new(
+            "service.name",
+            Config::bot_name(),
+        )]))
+}
+
+fn tracer() -> Result<Tracer, TraceError> {
+    let config = trace_config();
+
+    let tracer = match Config::tracing_exporter() {
+        TracingExporter::None => local_tracer(),
+        TracingExporter::Jaeger => opentelemetry_jaeger::new_pipeline()
+            .with_service_name(Config::bot_name())
+            .with_agent_endpoint(Config::opentelemetry_endpoint())
+            .with_trace_config(config)
+            .install_simple()?,
+        // tonic needs the runtime to export, so spans are exported in batches from a task
+        // OTLP over http isn't supported, opentelemetry-otlp 0.6 only offers grpc
+        TracingExporter::Otlp => opentelemetry_otlp::new_pipeline()
+            .with_endpoint(Config::opentelemetry_endpoint())
+            .with_trace_config(config)
+            .with_tonic()
+            .install_batch(opentelemetry::runtime::Tokio)?,
+    };
+
+    Ok(tracer)
+}
@@ -0,0 +1,210 @@
+use std::convert::Infallible;
+use std::sync::Arc;
+
+use futures::Stream;
+use hyper::{Body, Method, Request, Response, StatusCode};
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use reqwest::Url;
+use teloxide::dispatching::stop_token::{AsyncStopFlag, AsyncStopToken};
+use teloxide::dispatching::update_listeners::{StatefulListener, UpdateListener};
+use teloxide::prelude::*;
+use teloxide::types::Update;
+use teloxide::RequestError;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+
+use crate::config::Config;
+use crate::http::{self, empty, telegram_error};
+use crate::{BOT, HTTP_CLIENT};
+
+/// Header Telegram sends the secret token in, with every update
+const SECRET_HEADER: &str = "X-Telegram-Bot-Api-Secret-Token";
+
+/// Receives updates Telegram posts to the webhook url, and hands them to the dispatcher
+pub(crate) struct Webhook {
+    path: String,
+    secret: String,
+    updates: UnboundedSender<Update>,
+}
+
+struct State {
+    updates: UnboundedReceiver<Update>,
+    token: AsyncStopToken,
+    flag: AsyncStopFlag,
+    /// the listener was stopped, no new updates are accepted
+    stopped: bool,
+}
+
+#[derive(Serialize)]
+struct SetWebhook<'a> {
+    url: &'a str,
+    secret_token: &'a str,
+}
+
+#[derive(Deserialize)]
+struct TelegramResponse {
+    ok: bool,
+    description: Option<String>,
+}
+
+impl Webhook {
+    /// receive updates on `address`, and point Telegram at `url`
+    ///
+    /// The address is bound first, so Telegram isn't sent to an address nobody listens on.
+    pub(crate) async fn start(
+        url: &str,
+        address: &str,
+    ) -> anyhow::Result<impl UpdateListener<Infallible>> {
+        let (webhook, listener) = Webhook::new(url)?;
+        let webhook = Arc::new(webhook);
+
+        let server = {
+            let webhook = webhook.clone();
+            http::bind(address, move |req| {
+                let webhook = webhook.clone();
+                async move { webhook.receive(req).await }
+            })?
+        };
+        tokio::spawn(server);
+
+        webhook.register(url).await?;
+        Ok(listener)
+    }
+
+    /// create the webhook, and the listener its updates are dispatched from
+    fn new(url: &str) -> anyhow::Result<(Self, impl UpdateListener<Infallible>)> {
+        let path = Url::parse(url)?.path().to_owned();
+        // a random secret means updates for previous runs won't be accepted anymore
+        let secret = match Config::webhook_secret() {
+            Some(secret) => secret.to_owned(),
+            None => rand::thread_rng()
+                .sample_iter(&Alphanumeric)
+                .take(32)
+                .map(char::from)
+                .collect(),
+        };
+
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let (token, flag) = AsyncStopToken::new_pair();
+        let listener = StatefulListener::new(
+            State {
+                updates: receiver,
+                token,
+                flag,
+                stopped: false,
+            },
+            stream,
+            |state: &mut State| state.token.clone(),
+        );
+
+        Ok((
+            Self {
+                path,
+                secret,
+                updates: sender,
+            },
+            listener,
+        ))
+    }
+
+    async fn register(&self, url: &str) -> anyhow::Result<()> {
+        // teloxide doesn't support the secret token yet, so the request is sent by hand
+        let mut api_url = BOT.inner().api_url();
+        api_url.set_path(&format!("bot{}/setWebhook", BOT.inner().token()));
+        let resp: TelegramResponse = async {
+            HTTP_CLIENT
+                .post(api_url)
+                .json(&SetWebhook {
+                    url,
+                    secret_token: &self.secret,
+                })
+                .send()
+                .await?
+                .json()
+                .await
+        }
+        .await
+        // the error contains the url, and with it the bot token
+        .map_err(|err| {
+            anyhow::anyhow!(
+                "unable to register the webhook: {}",
+                telegram_error(&RequestError::NetworkError(err))
+            )
+        })?;
+
+        if !resp.ok {
+            anyhow::bail!(
+                "Telegram refused the webhook: {}",
+                resp.description.unwrap_or_default()
+            );
+        }
+        info!("registered webhook at {}", url);
+
+        Ok(())
+    }
+
+    /// stop Telegram from sending updates to an address that's going away
+    pub(crate) async fn unregister() {
+        match BOT.delete_webhook().await {
+            Ok(_) => info!("removed webhook"),
+            Err(err) => error!("unable to remove webhook: {}", telegram_error(&err)),
+        }
+    }
+
+    #[tracing::instrument(name = "webhook::receive", skip(self, req))]
+    async fn receive(&self, req: Request<Body>) -> Response<Body> {
+        if req.method() != Method::POST || req.uri().path() != self.path {
+            return empty(StatusCode::NOT_FOUND);
+        }
+
+        let secret = req
+            .headers()
+            .get(SECRET_HEADER)
+            .and_then(|secret| secret.to_str().ok());
+        if secret != Some(self.secret.as_str()) {
+            warn!("refused a webhook request without the right secret token");
+            return empty(StatusCode::UNAUTHORIZED);
+        }
+
+        let body = match hyper::body::to_bytes(req.into_body()).await {
+            Ok(body) => body,
+            Err(err) => {
+                error!("unable to read webhook request: {}", err);
+                return empty(StatusCode::BAD_REQUEST);
+            }
+        };
+
+        // Telegram keeps sending updates it gets an error for, so ones we can't parse are dropped
+        match serde_json::from_slice::<Update>(&body) {
+            Ok(update) => {
+                // shutting down, Telegram sends it again once the bot is back
+                if self.updates.send(update).is_err() {
+                    return empty(StatusCode::SERVICE_UNAVAILABLE);
+                }
+            }
+            Err(err) => error!("unable to parse update: {}", err),
+        }
+
+        empty(StatusCode::OK)
+    }
+}
+
+/// the updates received until the listener is stopped
+///
+/// Once it's stopped, Telegram gets an error for new updates so it sends them again after a
+/// restart, the updates that were already accepted are still handed out.
+fn stream(state: &mut State) -> impl Stream<Item = Result<Update, Infallible>> + '_ {
+    futures::stream::unfold(state, |state| async move {
+        if !state.stopped {
+            tokio::select! {
+                update = state.updates.recv() => return update.map(|update| (Ok(update), state)),
+                _ = &mut state.flag => {
+                    state.updates.close();
+                    state.stopped = true;
+                }
+            }
+        }
+
+        state.updates.recv().await.map(|update| (Ok(update), state))
+    })
+}